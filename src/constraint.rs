@@ -0,0 +1,1463 @@
+// Constraints
+// rules on particular cells, on top of the units - each is a list of cells with a rule
+// about the values in them, where the n-th state counts as the number n (so 1..9 for
+// Sudoku)
+// a constraint works on the values of the cells, 0 if unsolved otherwise state+1 (as in
+// the brute-force search), and supplies
+//   a checker (broken) used by validate,
+//   a pruning hook (allowed) used by the search and by the candidates of each cell
+//   (and allowed_all, where the search is quicker working out every cell at once),
+//   a propagator (eliminate) used by the logical techniques
+// and the technique whose steps its propagator makes
+// in a .sud file each constraint is a line starting with its kind, after the config line
+// a new kind of constraint is a variant here with those parts, plus its line syntax
+
+use std::collections::HashSet;
+
+use crate::{GridError, Json, Snumb, Technique};
+
+/// Constraint is an extra rule on a group of cells, given by their addresses
+#[derive(Clone, Debug, PartialEq)]
+pub enum Constraint {
+    Cage { sum: Option<u32>, cells: Vec<usize> }, // killer cage: no repeats, adding up to sum
+    Calc { op: CageOp, target: u64, cells: Vec<usize> }, // calcudoku cage: the cells make target
+                                                         // by op (repeats apart from in a unit)
+    Thermo { cells: Vec<usize> },                 // strictly increasing from the bulb, cells[0]
+    Arrow { cells: Vec<usize> },                  // circle cells[0] is the sum along the arrow
+    Sandwich { sum: u32, cells: Vec<usize> },     // row or column: sum between lowest and highest
+    Pair { rule: PairRule, cells: Vec<usize> },   // dot or sign between two cells
+    Negative { rules: Vec<PairRule>, cells: Vec<usize> }, // neighbours with no dot don't meet
+                                                          // the rules, cells in pairs
+    Skyscraper { count: u32, cells: Vec<usize> }, // row or column from the clue: count seen
+    XSum { sum: u32, cells: Vec<usize> },         // from the clue: the first (its value) add to sum
+    LittleKiller { sum: u32, cells: Vec<usize> }, // diagonal from the clue adding up to sum
+    Parity { odd: bool, cells: Vec<usize> },      // shaded cells: odd values only (or even)
+    Only { states: u64, cells: Vec<usize> },      // given candidates: only these states (bitmask)
+}
+
+/// PairRule is what a dot or sign between two cells says about their values
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PairRule {
+    White,   // kropki white dot: consecutive
+    Black,   // kropki black dot: one double the other
+    X,       // adding up to 10
+    V,       // adding up to 5
+    Greater, // the first greater than the second (Futoshiki sign)
+}
+
+/// CageOp is how the values of a calcudoku (KenKen) cage combine to its target
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CageOp {
+    Add, // the total
+    Sub, // the largest less the rest
+    Mul, // the product
+    Div, // the largest divided by the rest
+}
+
+/// Every kind of constraint, as it starts a line of a .sud file
+pub const CONSTRAINT_KINDS: [&str; 14] = ["cage", "thermo", "arrow", "sandwich", "kropki", "xv", "greater", "negative",
+    "odd", "even", "only", "skyscraper", "xsum", "littlekiller"];
+
+/// Most steps worked through when trying every way of filling a row or column for a
+/// skyscraper clue, beyond which a rougher bound on each cell is used instead
+const LINE_BUDGET: usize = 20_000;
+
+impl PairRule {
+    /// name - name of the rule, as used in .sud files
+    pub fn name(&self) -> &'static str {
+        match self {
+            PairRule::White => "white",
+            PairRule::Black => "black",
+            PairRule::X => "x",
+            PairRule::V => "v",
+            PairRule::Greater => "greater",
+        }
+    }
+
+    /// family - kind of constraint the rule belongs to: kropki, xv or greater
+    pub fn family(&self) -> &'static str {
+        match self {
+            PairRule::White | PairRule::Black => "kropki",
+            PairRule::X | PairRule::V => "xv",
+            PairRule::Greater => "greater",
+        }
+    }
+
+    /// holds - whether values a and b (of the first and second cell) meet the rule
+    pub fn holds(&self, a: i64, b: i64) -> bool {
+        match self {
+            PairRule::White => (a - b).abs() == 1,
+            PairRule::Black => a == 2 * b || b == 2 * a,
+            PairRule::X => a + b == 10,
+            PairRule::V => a + b == 5,
+            PairRule::Greater => a > b,
+        }
+    }
+
+    // family_rules - the rules of a family, as named after `negative` (not greater)
+    fn family_rules(family: &str) -> Option<Vec<PairRule>> {
+        match family {
+            "kropki" => Some(vec![PairRule::White, PairRule::Black]),
+            "xv" => Some(vec![PairRule::X, PairRule::V]),
+            _ => None,
+        }
+    }
+}
+
+impl CageOp {
+    /// symbol - the sign of the operation, as written after the target in .sud files
+    /// (`*`, `×`, `÷` and `−` are read too)
+    pub fn symbol(&self) -> char {
+        match self {
+            CageOp::Add => '+',
+            CageOp::Sub => '-',
+            CageOp::Mul => 'x',
+            CageOp::Div => '/',
+        }
+    }
+
+    /// from_symbol - look up an operation by its sign
+    pub fn from_symbol(symbol: char) -> Option<CageOp> {
+        match symbol {
+            '+' => Some(CageOp::Add),
+            '-' | '−' => Some(CageOp::Sub),
+            'x' | '*' | '×' => Some(CageOp::Mul),
+            '/' | '÷' => Some(CageOp::Div),
+            _ => None,
+        }
+    }
+
+    /// apply - what values (all of a cage's) come to, None if not a whole number from 0
+    pub fn apply(&self, values: &[u64]) -> Option<u64> {
+        let largest = values.iter().copied().max()?;
+        let at = values.iter().position(|&v| v == largest)?;
+        let mut rest = values.iter().enumerate().filter(|&(k, _)| k != at).map(|(_, &v)| v);
+        match self {
+            CageOp::Add => Some(values.iter().sum()),
+            CageOp::Sub => largest.checked_sub(rest.sum()),
+            CageOp::Mul => values.iter().try_fold(1u64, |p, &v| p.checked_mul(v)),
+            CageOp::Div => {
+                let divisor = rest.try_fold(1u64, |p, v| p.checked_mul(v))?;
+                largest.is_multiple_of(divisor).then(|| largest / divisor)
+            }
+        }
+    }
+}
+
+impl Constraint {
+    /// kind - name of the kind of constraint, as used in .sud files
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Constraint::Cage { .. } | Constraint::Calc { .. } => "cage",
+            Constraint::Thermo { .. } => "thermo",
+            Constraint::Arrow { .. } => "arrow",
+            Constraint::Sandwich { .. } => "sandwich",
+            Constraint::Pair { rule, .. } => rule.family(),
+            Constraint::Negative { .. } => "negative",
+            Constraint::Skyscraper { .. } => "skyscraper",
+            Constraint::XSum { .. } => "xsum",
+            Constraint::LittleKiller { .. } => "littlekiller",
+            Constraint::Parity { odd: true, .. } => "odd",
+            Constraint::Parity { odd: false, .. } => "even",
+            Constraint::Only { .. } => "only",
+        }
+    }
+
+    /// cells - addresses of every cell the constraint involves (in order along a line)
+    pub fn cells(&self) -> &[usize] {
+        match self {
+            Constraint::Cage { cells, .. }
+            | Constraint::Calc { cells, .. }
+            | Constraint::Thermo { cells }
+            | Constraint::Arrow { cells }
+            | Constraint::Sandwich { cells, .. }
+            | Constraint::Pair { cells, .. }
+            | Constraint::Negative { cells, .. }
+            | Constraint::Skyscraper { cells, .. }
+            | Constraint::XSum { cells, .. }
+            | Constraint::LittleKiller { cells, .. }
+            | Constraint::Parity { cells, .. }
+            | Constraint::Only { cells, .. } => cells,
+        }
+    }
+
+    /// technique - the logical technique that uses the constraint's propagator (None when
+    /// the candidates leave nothing for it, as for restrictions on single cells)
+    pub fn technique(&self) -> Option<Technique> {
+        match self {
+            Constraint::Cage { .. } => Some(Technique::CageSum),
+            Constraint::Calc { .. } => Some(Technique::CageArithmetic),
+            Constraint::Thermo { .. } => Some(Technique::Thermometer),
+            Constraint::Arrow { .. } => Some(Technique::ArrowSum),
+            Constraint::Sandwich { .. } => Some(Technique::SandwichSum),
+            Constraint::Pair { rule: PairRule::Greater, .. } => Some(Technique::GreaterThan),
+            Constraint::Pair { .. } | Constraint::Negative { .. } => Some(Technique::Dots),
+            Constraint::Skyscraper { .. } => Some(Technique::Skyscraper),
+            Constraint::XSum { .. } => Some(Technique::XSum),
+            Constraint::LittleKiller { .. } => Some(Technique::LittleKiller),
+            Constraint::Parity { .. } | Constraint::Only { .. } => None,
+        }
+    }
+
+    /// rule - what the constraint asks for, to explain its eliminations
+    pub fn rule(&self) -> String {
+        match self {
+            Constraint::Cage { sum, .. } => sum.map_or("avoiding repeats".to_owned(), |s| format!("making {}", s)),
+            Constraint::Calc { .. } => format!("making {}", self.label().unwrap_or_default()),
+            Constraint::Thermo { .. } => "increasing from the bulb".to_owned(),
+            Constraint::Arrow { .. } => "adding up to the circle".to_owned(),
+            Constraint::Sandwich { sum, .. } => format!("sandwiching {} between the lowest and highest", sum),
+            Constraint::Pair { rule, .. } => match rule {
+                PairRule::White => "being consecutive".to_owned(),
+                PairRule::Black => "one being double the other".to_owned(),
+                PairRule::X => "adding up to 10".to_owned(),
+                PairRule::V => "adding up to 5".to_owned(),
+                PairRule::Greater => "the first being greater".to_owned(),
+            },
+            Constraint::Negative { rules, .. } => format!("no {} between neighbours with no dot", rules[0].family()),
+            Constraint::Skyscraper { count, .. } => format!("seeing {} from outside", count),
+            Constraint::XSum { sum, .. } => format!("the first so many adding up to {}", sum),
+            Constraint::LittleKiller { sum, .. } => format!("adding up to {}", sum),
+            Constraint::Parity { odd, .. } => if *odd { "being odd" } else { "being even" }.to_owned(),
+            Constraint::Only { states, .. } => format!("being one of {}", value_list(*states)),
+        }
+    }
+
+    /// is_cage - whether this is a cage, killer or calcudoku
+    pub fn is_cage(&self) -> bool {
+        matches!(self, Constraint::Cage { .. } | Constraint::Calc { .. })
+    }
+
+    /// cage_sum - what a cage adds up to (None for a cage with no sum or another operation,
+    /// and for other constraints)
+    pub fn cage_sum(&self) -> Option<u32> {
+        match self {
+            Constraint::Cage { sum, .. } => *sum,
+            Constraint::Calc { op: CageOp::Add, target, .. } => u32::try_from(*target).ok(),
+            _ => None,
+        }
+    }
+
+    /// map_cells - move the constraint with the cells, address a going to `to(a)`
+    pub fn map_cells(&mut self, to: impl Fn(usize) -> usize) {
+        match self {
+            Constraint::Cage { cells, .. }
+            | Constraint::Calc { cells, .. }
+            | Constraint::Parity { cells, .. }
+            | Constraint::Only { cells, .. } => {
+                for a in cells.iter_mut() {
+                    *a = to(*a);
+                }
+                cells.sort_unstable();
+            }
+            Constraint::Thermo { cells }
+            | Constraint::Arrow { cells }
+            | Constraint::Sandwich { cells, .. }
+            | Constraint::Pair { cells, .. }
+            | Constraint::Negative { cells, .. }
+            | Constraint::Skyscraper { cells, .. }
+            | Constraint::XSum { cells, .. }
+            | Constraint::LittleKiller { cells, .. } => {
+                for a in cells.iter_mut() {
+                    *a = to(*a); // order along the line matters
+                }
+            }
+        }
+    }
+
+    /// parse - constraint from a line of a .sud file for a grid of n states, None if the
+    /// line doesn't start with a kind of constraint
+    /// cells are r<row>c<col>, numbered from 1
+    /// `cage <sum> <cell>...` (? for a cage with no sum)
+    /// `cage <target><op> <cell>...`, op one of + - x / (a calcudoku cage)
+    /// `thermo <bulb> <cell>...`
+    /// `arrow <circle> <cell>...`
+    /// `sandwich row|col <number> <sum>`
+    /// `kropki white|black <cell> <cell>`, `xv x|v <cell> <cell>`
+    /// `greater <cell> <cell>` (the first the greater)
+    /// `negative kropki|xv` (neighbours with no dot of that kind don't meet its rules)
+    /// `odd <cell>...`, `even <cell>...`, `only <value>,<value>... <cell>...`
+    /// outside clues sit on the ring of cells around the grid, row or column 0 or n+1:
+    /// `skyscraper <ring cell> <count>`, `xsum <ring cell> <sum>` (looking along the row or
+    /// column), `littlekiller <ring cell> ne|nw|se|sw <sum>` (along the diagonal that way)
+    pub fn parse(line: &str, n: usize) -> Option<Result<Constraint, GridError>> {
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if !tokens.first().is_some_and(|t| CONSTRAINT_KINDS.contains(t)) {
+            return None;
+        }
+        let bad = || GridError::BadConstraint(line.trim().to_owned());
+        let cells_from = |k: usize| -> Option<Vec<usize>> {
+            let cells: Option<Vec<usize>> = tokens.iter().skip(k).map(|t| parse_cell(t, n)).collect();
+            cells.filter(|c| !c.is_empty())
+        };
+        let constraint = match tokens[..] {
+            ["cage", sum, ..] => cells_from(2).and_then(|mut cells| {
+                cells.sort_unstable();
+                cells.dedup();
+                let op = sum.char_indices().next_back().and_then(|(i, c)| CageOp::from_symbol(c).map(|op| (i, op)));
+                match (sum, op) {
+                    ("?", _) => Some(Constraint::Cage { sum: None, cells }),
+                    (_, Some((i, op))) => sum[..i].parse().ok().map(|target| Constraint::Calc { op, target, cells }),
+                    _ => sum.parse().ok().map(|sum| Constraint::Cage { sum: Some(sum), cells }),
+                }
+            }),
+            ["thermo", ..] => cells_from(1).map(|cells| Constraint::Thermo { cells }),
+            ["arrow", ..] => cells_from(1).filter(|c| c.len() > 1).map(|cells| Constraint::Arrow { cells }),
+            ["sandwich", line, number, sum] => {
+                let k = number.parse::<usize>().ok().filter(|k| (1..=n).contains(k)).map(|k| k - 1);
+                let cells: Option<Vec<usize>> = match (line, k) {
+                    ("row", Some(k)) => Some((0..n).map(|col| k * n + col).collect()),
+                    ("col", Some(k)) => Some((0..n).map(|row| row * n + k).collect()),
+                    _ => None,
+                };
+                cells.zip(sum.parse().ok()).map(|(cells, sum)| Constraint::Sandwich { sum, cells })
+            }
+            [family @ ("kropki" | "xv"), name, _, _] => {
+                let rule = [PairRule::White, PairRule::Black, PairRule::X, PairRule::V].into_iter()
+                    .find(|r| r.family() == family && r.name() == name);
+                rule.zip(cells_from(2)).filter(|(_, cells)| adjacent(cells[0], cells[1], n))
+                    .map(|(rule, cells)| Constraint::Pair { rule, cells })
+            }
+            ["greater", _, _] => cells_from(1).filter(|cells| adjacent(cells[0], cells[1], n))
+                .map(|cells| Constraint::Pair { rule: PairRule::Greater, cells }),
+            ["negative", family] => PairRule::family_rules(family).map(|rules| Constraint::Negative { rules, cells: Vec::new() }),
+            [kind @ ("skyscraper" | "xsum"), ring, clue] => {
+                let cells = parse_ring(ring, n).and_then(|(row, col)| ring_line(row, col, n));
+                match (kind, clue.parse().ok()) {
+                    ("skyscraper", Some(count)) => cells.map(|cells| Constraint::Skyscraper { count, cells }),
+                    (_, Some(sum)) => cells.map(|cells| Constraint::XSum { sum, cells }),
+                    _ => None,
+                }
+            }
+            ["littlekiller", ring, dir, sum] => {
+                let step = match dir {
+                    "ne" => Some((-1, 1)),
+                    "nw" => Some((-1, -1)),
+                    "se" => Some((1, 1)),
+                    "sw" => Some((1, -1)),
+                    _ => None,
+                };
+                let cells = parse_ring(ring, n).zip(step).map(|((row, col), step)| ring_diagonal(row, col, step, n));
+                cells.filter(|c| c.len() > 1).zip(sum.parse().ok()).map(|(cells, sum)| Constraint::LittleKiller { sum, cells })
+            }
+            [kind @ ("odd" | "even"), ..] => cells_from(1).map(|mut cells| {
+                cells.sort_unstable();
+                cells.dedup();
+                Constraint::Parity { odd: kind == "odd", cells }
+            }),
+            ["only", list, ..] => {
+                let states = list.split(',').try_fold(0u64, |mask, v| {
+                    v.parse::<usize>().ok().filter(|v| (1..=n).contains(v)).map(|v| mask | 1u64 << (v - 1))
+                });
+                states.zip(cells_from(2)).map(|(states, mut cells)| {
+                    cells.sort_unstable();
+                    cells.dedup();
+                    Constraint::Only { states, cells }
+                })
+            }
+            _ => None,
+        };
+        Some(constraint.ok_or_else(bad))
+    }
+
+    /// to_text - the constraint as a line of a .sud file
+    pub fn to_text(&self, n: usize) -> String {
+        let cells: Vec<String> = self.cells().iter().map(|&a| format!("r{}c{}", a / n + 1, a % n + 1)).collect();
+        match self {
+            Constraint::Cage { sum, .. } => {
+                let sum = sum.map_or("?".to_owned(), |s| s.to_string());
+                format!("cage {} {}", sum, cells.join(" "))
+            }
+            Constraint::Calc { .. } => format!("cage {} {}", self.label().unwrap_or_default(), cells.join(" ")),
+            Constraint::Thermo { .. } | Constraint::Arrow { .. } => format!("{} {}", self.kind(), cells.join(" ")),
+            Constraint::Pair { rule: PairRule::Greater, .. } => format!("greater {}", cells.join(" ")),
+            Constraint::Pair { rule, .. } => format!("{} {} {}", rule.family(), rule.name(), cells.join(" ")),
+            Constraint::Negative { rules, .. } => format!("negative {}", rules[0].family()),
+            Constraint::Skyscraper { count: clue, .. } | Constraint::XSum { sum: clue, .. } => {
+                let (row, col) = self.ring(n).unwrap_or_default();
+                format!("{} r{}c{} {}", self.kind(), row, col, clue)
+            }
+            Constraint::LittleKiller { sum, cells } => {
+                let (row, col) = self.ring(n).unwrap_or_default();
+                let dir = match (cells[1] / n > cells[0] / n, cells[1] % n > cells[0] % n) {
+                    (false, true) => "ne",
+                    (false, false) => "nw",
+                    (true, true) => "se",
+                    (true, false) => "sw",
+                };
+                format!("littlekiller r{}c{} {} {}", row, col, dir, sum)
+            }
+            Constraint::Parity { .. } => format!("{} {}", self.kind(), cells.join(" ")),
+            Constraint::Only { states, .. } => format!("only {} {}", value_list(*states), cells.join(" ")),
+            Constraint::Sandwich { sum, cells } => {
+                if cells.iter().all(|&a| a / n == cells[0] / n) {
+                    format!("sandwich row {} {}", cells[0] / n + 1, sum)
+                } else {
+                    format!("sandwich col {} {}", cells[0] % n + 1, sum)
+                }
+            }
+        }
+    }
+
+    /// label - short note of the clue, for drawing on the grid (None if nothing to show)
+    pub fn label(&self) -> Option<String> {
+        match self {
+            Constraint::Cage { sum, .. } => sum.map(|s| s.to_string()),
+            Constraint::Calc { op, target, .. } => Some(format!("{}{}", target, op.symbol())),
+            _ => None,
+        }
+    }
+
+    /// ring - where an outside clue sits on the ring of cells around a grid of n states, as
+    /// (row, col) counting the ring as row and column 0 and n+1 (None for other constraints)
+    pub fn ring(&self, n: usize) -> Option<(usize, usize)> {
+        match self {
+            Constraint::Skyscraper { cells, .. } | Constraint::XSum { cells, .. } | Constraint::LittleKiller { cells, .. } => {
+                // one step back from the first cell, the way the line goes
+                let (row, col) = (cells[0] / n + 1, cells[0] % n + 1);
+                let back = |at: usize, next: usize| (2 * at).checked_sub(next).filter(|&b| b <= n + 1);
+                Some((back(row, cells[1] / n + 1)?, back(col, cells[1] % n + 1)?))
+            }
+            Constraint::Sandwich { cells, .. } if cells[1] == cells[0] + 1 => Some((cells[0] / n + 1, 0)),
+            Constraint::Sandwich { cells, .. } => Some((0, cells[0] % n + 1)),
+            _ => None,
+        }
+    }
+
+    /// clue - the outside clue as drawn on the ring, with an arrow along a little killer's
+    /// diagonal (None for other constraints)
+    pub fn clue(&self, n: usize) -> Option<String> {
+        match self {
+            Constraint::Sandwich { sum: clue, .. }
+            | Constraint::Skyscraper { count: clue, .. }
+            | Constraint::XSum { sum: clue, .. } => Some(clue.to_string()),
+            Constraint::LittleKiller { sum, cells } => {
+                let arrow = match (cells[1] / n > cells[0] / n, cells[1] % n > cells[0] % n) {
+                    (false, true) => '↗',
+                    (false, false) => '↖',
+                    (true, true) => '↘',
+                    (true, false) => '↙',
+                };
+                Some(format!("{}{}", sum, arrow))
+            }
+            _ => None,
+        }
+    }
+
+    /// broken - whether the values of a grid of n states already break the constraint,
+    /// giving the cells to blame and why
+    pub fn broken(&self, values: &[u8], n: usize) -> Option<(Vec<usize>, String)> {
+        match self {
+            Constraint::Cage { sum, cells } => {
+                for (k, &a) in cells.iter().enumerate() {
+                    let repeats: Vec<usize> = cells[k..].iter().copied()
+                        .filter(|&b| values[a] != 0 && values[b] == values[a]).collect();
+                    if repeats.len() > 1 {
+                        return Some((repeats, format!("repeats {}", values[a])));
+                    }
+                }
+                let sum = (*sum)?;
+                let total: u32 = cells.iter().map(|&a| values[a] as u32).sum();
+                if cells.iter().all(|&a| values[a] != 0) {
+                    (total != sum).then(|| (cells.clone(), format!("adds up to {} rather than {}", total, sum)))
+                } else {
+                    // no way left of making the sum
+                    (!self.misfits(values, n).is_empty()).then(|| (cells.clone(), format!("can't add up to {}", sum)))
+                }
+            }
+            Constraint::Calc { op, target, cells } => {
+                let all: Option<Vec<u64>> = cells.iter().map(|&a| (values[a] != 0).then_some(values[a] as u64)).collect();
+                let label = self.label().unwrap_or_default();
+                match all {
+                    Some(all) => {
+                        let made = op.apply(&all);
+                        (made != Some(*target)).then(|| {
+                            let made = made.map_or("nothing".to_owned(), |m| m.to_string());
+                            (cells.clone(), format!("makes {} rather than {}", made, label))
+                        })
+                    }
+                    None => (!self.misfits(values, n).is_empty()).then(|| (cells.clone(), format!("can't make {}", label))),
+                }
+            }
+            _ => {
+                let misfits = self.misfits(values, n);
+                let why = match self {
+                    Constraint::Thermo { .. } => "doesn't increase from the bulb".to_owned(),
+                    Constraint::Arrow { .. } => "doesn't add up to its circle".to_owned(),
+                    Constraint::Sandwich { sum, .. } => format!("can't fit {} between the lowest and highest", sum),
+                    Constraint::Skyscraper { count, .. } => format!("can't see {} from outside", count),
+                    Constraint::XSum { sum, .. } => format!("can't make {} from the first so many", sum),
+                    Constraint::LittleKiller { sum, .. } => format!("doesn't add up to {}", sum),
+                    Constraint::Pair { .. } => format!("isn't met, {}", self.rule()),
+                    Constraint::Negative { rules, .. } => format!("has a {} pair with no dot", rules[0].family()),
+                    Constraint::Parity { odd, .. } => if *odd { "isn't odd" } else { "isn't even" }.to_owned(),
+                    Constraint::Only { states, .. } => format!("isn't one of {}", value_list(*states)),
+                    Constraint::Cage { .. } | Constraint::Calc { .. } => "can't be completed".to_owned(),
+                };
+                (!misfits.is_empty()).then_some((misfits, why))
+            }
+        }
+    }
+
+    // misfits - the solved cells whose values the constraint doesn't allow, given the rest
+    fn misfits(&self, values: &[u8], n: usize) -> Vec<usize> {
+        self.cells().iter().copied()
+            .filter(|&a| values[a] != 0 && self.allowed(a, values, n) & 1u64 << (values[a] - 1) == 0)
+            .collect()
+    }
+
+    /// allowed - bitmask of the states which the constraint still allows at address, given
+    /// the values of the other cells (address is one of its cells)
+    pub fn allowed(&self, address: usize, values: &[u8], n: usize) -> u64 {
+        let all = if n == 64 { u64::MAX } else { (1u64 << n) - 1 };
+        match self {
+            Constraint::Cage { sum, cells } => {
+                let mut used = 0u64;
+                let mut total = 0u32;
+                let mut open = 0;
+                for &a in cells {
+                    if a == address {
+                        continue;
+                    }
+                    if values[a] == 0 {
+                        open += 1;
+                    } else {
+                        used |= 1u64 << (values[a] - 1);
+                        total += values[a] as u32;
+                    }
+                }
+                let mut allowed = all & !used;
+                if let Some(sum) = *sum {
+                    // each state has to leave a sum the open cells can make from the values left
+                    let mut mask = allowed;
+                    while mask != 0 {
+                        let s = mask.trailing_zeros();
+                        mask &= mask - 1;
+                        let rest = sum as i64 - total as i64 - (s + 1) as i64;
+                        let left = all & !used & !(1u64 << s);
+                        let (low, high) = extreme_sums(left, open);
+                        if rest < low || rest > high {
+                            allowed &= !(1u64 << s);
+                        }
+                    }
+                }
+                allowed
+            }
+            Constraint::Calc { op, target, cells } => {
+                let options: Vec<u64> = cells.iter()
+                    .map(|&a| if values[a] == 0 || a == address { all } else { 1u64 << (values[a] - 1) })
+                    .collect();
+                let seen = calc_seen(*op, *target, cells, &options, n);
+                cells.iter().position(|&a| a == address).map_or(all, |k| seen[k])
+            }
+            Constraint::Thermo { cells } => {
+                // each step along from the bulb is at least one more
+                let len = cells.len() as i64;
+                let p = cells.iter().position(|&a| a == address).unwrap_or(0) as i64;
+                let (mut low, mut high) = (p + 1, n as i64 - (len - 1 - p));
+                for (j, &a) in cells.iter().enumerate() {
+                    let (j, v) = (j as i64, values[a] as i64);
+                    if v == 0 || j == p {
+                        continue;
+                    }
+                    if j < p {
+                        low = low.max(v + p - j);
+                    } else {
+                        high = high.min(v - (j - p));
+                    }
+                }
+                value_range(low, high, n)
+            }
+            Constraint::Arrow { cells } => {
+                let solved = |a: usize| a != address && values[a] != 0;
+                let line = &cells[1..];
+                let total: i64 = line.iter().filter(|&&a| solved(a)).map(|&a| values[a] as i64).sum();
+                let open = line.iter().filter(|&&a| a != address && values[a] == 0).count() as i64;
+                if address == cells[0] {
+                    value_range(total + open, total + open * n as i64, n)
+                } else if solved(cells[0]) {
+                    let circle = values[cells[0]] as i64;
+                    value_range(circle - total - open * n as i64, circle - total - open, n)
+                } else {
+                    value_range(1, n as i64 - total - open, n)
+                }
+            }
+            Constraint::Sandwich { .. } => {
+                (0..n).filter(|&s| self.sandwich_fits(values, n, Some((address, s as u8 + 1))))
+                    .fold(0, |mask, s| mask | 1u64 << s)
+            }
+            Constraint::Skyscraper { count, cells } => {
+                let options = line_options(cells, values, Some(address), n);
+                let k = cells.iter().position(|&a| a == address).unwrap_or(0);
+                sky_seen(*count, &options, n).map_or(options[k] & sky_bound(*count, k, n), |seen| seen[k])
+            }
+            Constraint::XSum { sum, cells } => {
+                let options = line_options(cells, values, Some(address), n);
+                let k = cells.iter().position(|&a| a == address).unwrap_or(0);
+                xsum_seen(*sum, &options)[k]
+            }
+            Constraint::LittleKiller { sum, cells } => {
+                let total: i64 = cells.iter().filter(|&&a| a != address).map(|&a| values[a] as i64).sum();
+                let open = cells.iter().filter(|&&a| a != address && values[a] == 0).count() as i64;
+                value_range(*sum as i64 - total - open * n as i64, *sum as i64 - total - open, n)
+            }
+            Constraint::Pair { rule, cells } => {
+                let all = if n == 64 { u64::MAX } else { (1u64 << n) - 1 };
+                let (first, other) = if address == cells[0] { (true, cells[1]) } else { (false, cells[0]) };
+                let others = if values[other] == 0 { all } else { 1u64 << (values[other] - 1) };
+                pair_keep(*rule, first, others, n)
+            }
+            Constraint::Negative { rules, cells } => {
+                let all = if n == 64 { u64::MAX } else { (1u64 << n) - 1 };
+                cells.chunks(2)
+                    .filter_map(|pair| match *pair {
+                        [a, b] if a == address && values[b] != 0 => Some(values[b]),
+                        [a, b] if b == address && values[a] != 0 => Some(values[a]),
+                        _ => None,
+                    })
+                    .fold(all, |mask, w| mask & negative_keep(rules, 1u64 << (w - 1), n))
+            }
+            Constraint::Parity { odd, .. } => (0..n).filter(|s| (s % 2 == 0) == *odd).fold(0, |mask, s| mask | 1u64 << s),
+            Constraint::Only { states, .. } => states & all,
+        }
+    }
+
+    /// allowed_all - allowed for every one of the cells at once (as if each were unsolved),
+    /// where one piece of work gives them all, as for calcudoku cages and rows or columns
+    /// seen from outside - None where asking
+    /// for each cell in turn is no slower
+    pub fn allowed_all(&self, values: &[u8], n: usize) -> Option<Vec<u64>> {
+        match self {
+            Constraint::Calc { op, target, cells } => {
+                let all = if n == 64 { u64::MAX } else { (1u64 << n) - 1 };
+                let options: Vec<u64> = cells.iter()
+                    .map(|&a| if values[a] == 0 { all } else { 1u64 << (values[a] - 1) })
+                    .collect();
+                Some(calc_seen(*op, *target, cells, &options, n))
+            }
+            Constraint::Skyscraper { count, cells } => {
+                let options = line_options(cells, values, None, n);
+                Some(sky_seen(*count, &options, n).unwrap_or_else(|| {
+                    options.iter().enumerate().map(|(k, &o)| o & sky_bound(*count, k, n)).collect()
+                }))
+            }
+            Constraint::XSum { sum, cells } => Some(xsum_seen(*sum, &line_options(cells, values, None, n))),
+            _ => None,
+        }
+    }
+
+    // sandwich_fits - whether the cells between the lowest and highest states (if both are
+    // placed, with address taken to hold value) can still make the sum
+    fn sandwich_fits(&self, values: &[u8], n: usize, test: Option<(usize, u8)>) -> bool {
+        let Constraint::Sandwich { sum, cells } = self else {
+            return true;
+        };
+        let value = |a: usize| match test {
+            Some((address, v)) if address == a => v,
+            _ => values[a],
+        };
+        let low = cells.iter().position(|&a| value(a) == 1);
+        let high = cells.iter().position(|&a| value(a) as usize == n);
+        let (Some(low), Some(high)) = (low, high) else {
+            return true;
+        };
+        let between = &cells[low.min(high) + 1..low.max(high)];
+        let total: i64 = between.iter().map(|&a| value(a) as i64).sum();
+        let open = between.iter().filter(|&&a| value(a) == 0).count();
+        let all = if n == 64 { u64::MAX } else { (1u64 << n) - 1 };
+        let used = cells.iter().filter(|&&a| value(a) != 0).fold(0u64, |m, &a| m | 1u64 << (value(a) - 1));
+        let (least, most) = extreme_sums(all & !used & !1 & !(1u64 << (n - 1)), open);
+        (total + least..=total + most).contains(&(*sum as i64))
+    }
+
+    /// eliminate - candidates (bitmask for each cell, 0 if solved) of a grid of n states
+    /// which no way of completing the constraint uses, as (address, state)
+    pub fn eliminate(&self, cands: &[u64], values: &[u8], n: usize) -> Vec<(usize, Snumb)> {
+        // the states each cell can keep, from its smallest and largest values
+        let ranges: Vec<(i64, i64)> = self.cells().iter().map(|&a| value_bounds(cands, values, a)).collect();
+        let keep: Vec<u64> = match self {
+            Constraint::Cage { sum: None, cells } => cells.iter().map(|&a| cands[a]).collect(),
+            Constraint::Cage { sum: Some(sum), cells } => {
+                let open: Vec<usize> = cells.iter().copied().filter(|&a| values[a] == 0).collect();
+                let used = cells.iter().filter(|&&a| values[a] != 0).fold(0u64, |m, &a| m | 1u64 << (values[a] - 1));
+                let total: u32 = cells.iter().map(|&a| values[a] as u32).sum();
+                let mut seen = vec![0u64; open.len()];
+                let mut chosen = vec![0; open.len()];
+                cage_fill(&open, cands, used, Some(*sum as i64 - total as i64), 0, &mut chosen, &mut seen);
+                cells.iter().map(|&a| open.iter().position(|&b| b == a).map_or(0, |k| seen[k])).collect()
+            }
+            Constraint::Calc { op, target, cells } => {
+                let options: Vec<u64> = cells.iter().map(|&a| options(cands, values, a)).collect();
+                calc_seen(*op, *target, cells, &options, n)
+            }
+            Constraint::Thermo { .. } => {
+                // push the smallest values up from the bulb and the largest down from the tip
+                let mut ranges = ranges;
+                for p in 1..ranges.len() {
+                    ranges[p].0 = ranges[p].0.max(ranges[p - 1].0 + 1);
+                }
+                for p in (0..ranges.len() - 1).rev() {
+                    ranges[p].1 = ranges[p].1.min(ranges[p + 1].1 - 1);
+                }
+                ranges.iter().map(|&(low, high)| value_range(low, high, n)).collect()
+            }
+            Constraint::Arrow { .. } => {
+                let (least, most) = ranges[1..].iter().fold((0, 0), |(l, m), &(low, high)| (l + low, m + high));
+                let (circle_low, circle_high) = ranges[0];
+                let mut keep = vec![value_range(circle_low.max(least), circle_high.min(most), n)];
+                for &(low, high) in &ranges[1..] {
+                    // the rest of the arrow makes at least least-low and at most most-high
+                    keep.push(value_range(low.max(circle_low - (most - high)), high.min(circle_high - (least - low)), n));
+                }
+                keep
+            }
+            Constraint::Sandwich { sum, cells } => {
+                // try every place for the lowest and highest states, filling the cells between
+                // them like a cage of the sum from the other states
+                let (top, bottom) = (0, n - 1);
+                let crusts = 1u64 << top | 1u64 << bottom;
+                let placed = cells.iter().filter(|&&a| values[a] != 0).fold(0u64, |m, &a| m | 1u64 << (values[a] - 1));
+                let can = |k: usize, s: usize| {
+                    values[cells[k]] as usize == s + 1 || (placed & 1u64 << s == 0 && cands[cells[k]] & 1u64 << s != 0)
+                };
+                let used = placed | crusts;
+                let mut keep = vec![0u64; cells.len()];
+                for low in (0..cells.len()).filter(|&k| can(k, top)) {
+                    for high in (0..cells.len()).filter(|&k| k != low && can(k, bottom)) {
+                        let between = low.min(high) + 1..low.max(high);
+                        if between.clone().any(|k| values[cells[k]] != 0 && crusts & 1u64 << (values[cells[k]] - 1) != 0) {
+                            continue;
+                        }
+                        let open: Vec<usize> = between.clone().map(|k| cells[k]).filter(|&a| values[a] == 0).collect();
+                        let total: i64 = between.clone().map(|k| values[cells[k]] as i64).sum();
+                        let mut seen = vec![0u64; open.len()];
+                        let mut chosen = vec![0; open.len()];
+                        cage_fill(&open, cands, used, Some(*sum as i64 - total), 0, &mut chosen, &mut seen);
+                        if seen.first().map_or(total != *sum as i64, |&s| s == 0) {
+                            continue; // no way of making the sum
+                        }
+                        keep[low] |= 1 << top;
+                        keep[high] |= 1 << bottom;
+                        let mut j = 0;
+                        for k in (0..cells.len()).filter(|&k| k != low && k != high) {
+                            if !between.contains(&k) {
+                                keep[k] |= cands[cells[k]] & !crusts;
+                            } else if values[cells[k]] == 0 {
+                                keep[k] |= seen[j];
+                                j += 1;
+                            }
+                        }
+                    }
+                }
+                keep
+            }
+            Constraint::Skyscraper { count, cells } => {
+                let options: Vec<u64> = cells.iter().map(|&a| options(cands, values, a)).collect();
+                sky_seen(*count, &options, n).unwrap_or_else(|| {
+                    options.iter().enumerate().map(|(k, &o)| o & sky_bound(*count, k, n)).collect()
+                })
+            }
+            Constraint::XSum { sum, cells } => {
+                xsum_seen(*sum, &cells.iter().map(|&a| options(cands, values, a)).collect::<Vec<_>>())
+            }
+            Constraint::LittleKiller { sum, .. } => {
+                let sum = *sum as i64;
+                let (least, most) = ranges.iter().fold((0, 0), |(l, m), &(low, high)| (l + low, m + high));
+                ranges.iter()
+                    .map(|&(low, high)| value_range(low.max(sum - (most - high)), high.min(sum - (least - low)), n))
+                    .collect()
+            }
+            Constraint::Pair { rule, cells } => vec![
+                pair_keep(*rule, true, options(cands, values, cells[1]), n),
+                pair_keep(*rule, false, options(cands, values, cells[0]), n),
+            ],
+            Constraint::Negative { rules, cells } => cells.chunks(2)
+                .flat_map(|pair| [
+                    negative_keep(rules, options(cands, values, pair[1]), n),
+                    negative_keep(rules, options(cands, values, pair[0]), n),
+                ])
+                .collect(),
+            Constraint::Parity { cells, .. } | Constraint::Only { cells, .. } => {
+                cells.iter().map(|&a| self.allowed(a, values, n)).collect()
+            }
+        };
+
+        let mut eliminated = Vec::new();
+        for (&a, &keep) in self.cells().iter().zip(&keep) {
+            let mut gone = cands[a] & !keep;
+            while gone != 0 {
+                eliminated.push((a, gone.trailing_zeros() as Snumb));
+                gone &= gone - 1;
+            }
+        }
+        eliminated.sort_unstable();
+        eliminated.dedup(); // a cell can be in several pairs
+        eliminated
+    }
+
+    /// to_json - the constraint, with its cells as row/col numbered from 1
+    pub fn to_json(&self, n: usize) -> Json {
+        let cells = Json::Arr(self.cells().iter().map(|&a| Json::Obj(vec![
+            ("row", Json::num(a / n + 1)),
+            ("col", Json::num(a % n + 1)),
+        ])).collect());
+        match self {
+            Constraint::Cage { sum, .. } => Json::Obj(vec![
+                ("kind", Json::str(self.kind())),
+                ("sum", sum.map_or(Json::Null, |s| Json::num(s as usize))),
+                ("cells", cells),
+            ]),
+            Constraint::Calc { op, target, .. } => Json::Obj(vec![
+                ("kind", Json::str(self.kind())),
+                ("target", Json::num(*target as usize)),
+                ("op", Json::str(op.symbol().to_string())),
+                ("cells", cells),
+            ]),
+            Constraint::Sandwich { sum, .. } | Constraint::XSum { sum, .. } | Constraint::LittleKiller { sum, .. } => {
+                Json::Obj(vec![
+                    ("kind", Json::str(self.kind())),
+                    ("sum", Json::num(*sum as usize)),
+                    ("cells", cells),
+                ])
+            }
+            Constraint::Skyscraper { count, .. } => Json::Obj(vec![
+                ("kind", Json::str(self.kind())),
+                ("count", Json::num(*count as usize)),
+                ("cells", cells),
+            ]),
+            Constraint::Thermo { .. } | Constraint::Arrow { .. } | Constraint::Parity { .. } => Json::Obj(vec![
+                ("kind", Json::str(self.kind())),
+                ("cells", cells),
+            ]),
+            Constraint::Pair { rule, .. } => Json::Obj(vec![
+                ("kind", Json::str(self.kind())),
+                ("rule", Json::str(rule.name())),
+                ("cells", cells),
+            ]),
+            Constraint::Negative { rules, .. } => Json::Obj(vec![
+                ("kind", Json::str(self.kind())),
+                ("of", Json::str(rules[0].family())),
+            ]),
+            Constraint::Only { states, .. } => Json::Obj(vec![
+                ("kind", Json::str(self.kind())),
+                ("values", Json::Arr((0..64).filter(|s| states & 1u64 << s != 0).map(|s| Json::num(s + 1)).collect())),
+                ("cells", cells),
+            ]),
+        }
+    }
+}
+
+/// name - describe constraint k of a list for messages, numbered among those of its
+/// kind, e.g. "cage 3"
+pub fn name(constraints: &[Constraint], k: usize) -> String {
+    let kind = constraints[k].kind();
+    let index = constraints[..k].iter().filter(|c| c.kind() == kind).count();
+    format!("{} {}", kind, index + 1)
+}
+
+/// refresh_negatives - work out again the pairs of neighbours (orthogonally adjacent
+/// cells) of each negative constraint: those with no dot of its kind between them
+/// (after a whole file of constraints has been added, rather than after each)
+pub fn refresh_negatives(constraints: &mut [Constraint], n: usize) {
+    for k in 0..constraints.len() {
+        let Constraint::Negative { rules, .. } = &constraints[k] else {
+            continue;
+        };
+        let dotted: HashSet<(usize, usize)> = constraints.iter()
+            .filter_map(|c| match c {
+                Constraint::Pair { rule, cells } if rules.contains(rule) => Some((cells[0].min(cells[1]), cells[0].max(cells[1]))),
+                _ => None,
+            })
+            .collect();
+        let mut pairs = Vec::new();
+        for a in 0..n * n {
+            for b in [a + 1, a + n] {
+                let next = if b == a + 1 { a % n + 1 < n } else { b < n * n };
+                if next && !dotted.contains(&(a, b)) {
+                    pairs.extend([a, b]);
+                }
+            }
+        }
+        if let Constraint::Negative { cells, .. } = &mut constraints[k] {
+            *cells = pairs;
+        }
+    }
+}
+
+/// parse_cell - address of a cell named r<row>c<col> (from 1) in a grid of n states
+pub fn parse_cell(token: &str, n: usize) -> Option<usize> {
+    let (row, col) = token.strip_prefix('r')?.split_once('c')?;
+    let (row, col): (usize, usize) = (row.parse().ok()?, col.parse().ok()?);
+    (1..=n).contains(&row).then_some(())?;
+    (1..=n).contains(&col).then_some((row - 1) * n + col - 1)
+}
+
+// adjacent - whether cells a and b of a grid of n states are orthogonal neighbours
+fn adjacent(a: usize, b: usize, n: usize) -> bool {
+    (a / n == b / n && (a % n).abs_diff(b % n) == 1) || (a % n == b % n && a.abs_diff(b) == n)
+}
+
+// parse_ring - row and col of a cell on the ring around a grid of n states, named
+// r<row>c<col> with row or col 0 or n+1
+fn parse_ring(token: &str, n: usize) -> Option<(usize, usize)> {
+    let (row, col) = token.strip_prefix('r')?.split_once('c')?;
+    let (row, col): (usize, usize) = (row.parse().ok()?, col.parse().ok()?);
+    let edge = |i: usize| i == 0 || i == n + 1;
+    (row <= n + 1 && col <= n + 1 && (edge(row) || edge(col))).then_some((row, col))
+}
+
+// ring_line - the cells of the row or column looked along from ring cell row, col, from
+// the clue inwards (None at a corner)
+fn ring_line(row: usize, col: usize, n: usize) -> Option<Vec<usize>> {
+    let inside = |i: usize| (1..=n).contains(&i);
+    match (row, col) {
+        (0, c) if inside(c) => Some((0..n).map(|r| r * n + c - 1).collect()),
+        (r, c) if r == n + 1 && inside(c) => Some((0..n).rev().map(|r| r * n + c - 1).collect()),
+        (r, 0) if inside(r) => Some((0..n).map(|c| (r - 1) * n + c).collect()),
+        (r, c) if c == n + 1 && inside(r) => Some((0..n).rev().map(|c| (r - 1) * n + c).collect()),
+        _ => None,
+    }
+}
+
+// ring_diagonal - the cells of the diagonal from ring cell row, col stepping by step
+fn ring_diagonal(row: usize, col: usize, step: (isize, isize), n: usize) -> Vec<usize> {
+    let mut cells = Vec::new();
+    let (mut row, mut col) = (row as isize + step.0, col as isize + step.1);
+    while (1..=n as isize).contains(&row) && (1..=n as isize).contains(&col) {
+        cells.push((row - 1) as usize * n + (col - 1) as usize);
+        (row, col) = (row + step.0, col + step.1);
+    }
+    cells
+}
+
+// value_range - bitmask of the states counting as low to high
+fn value_range(low: i64, high: i64, n: usize) -> u64 {
+    (low.max(1)..=high.min(n as i64)).fold(0, |mask, v| mask | 1u64 << (v - 1))
+}
+
+// value_list - the values of the states in mask, as 1,3,5
+fn value_list(mask: u64) -> String {
+    let values: Vec<String> = (0..64).filter(|s| mask & 1u64 << s != 0).map(|s| (s + 1).to_string()).collect();
+    values.join(",")
+}
+
+// options - bitmask of the states cell a can take (its value if solved)
+fn options(cands: &[u64], values: &[u8], a: usize) -> u64 {
+    if values[a] == 0 { cands[a] } else { 1u64 << (values[a] - 1) }
+}
+
+// pair_keep - the states of one cell of a pair (the first, or the second) meeting the rule
+// with some state of the other cell from others
+fn pair_keep(rule: PairRule, first: bool, others: u64, n: usize) -> u64 {
+    let mut keep = 0;
+    for v in 1..=n as i64 {
+        let fits = (1..=n as i64).any(|w| {
+            others & 1u64 << (w - 1) != 0 && if first { rule.holds(v, w) } else { rule.holds(w, v) }
+        });
+        if fits {
+            keep |= 1u64 << (v - 1);
+        }
+    }
+    keep
+}
+
+// negative_keep - the states of one cell of a pair with no dot, leaving some different
+// state of the other cell from others which meets none of the rules
+fn negative_keep(rules: &[PairRule], others: u64, n: usize) -> u64 {
+    let mut keep = 0;
+    for v in 1..=n as i64 {
+        let fits = (1..=n as i64).any(|w| {
+            w != v && others & 1u64 << (w - 1) != 0 && !rules.iter().any(|r| r.holds(v, w))
+        });
+        if fits {
+            keep |= 1u64 << (v - 1);
+        }
+    }
+    keep
+}
+
+// value_bounds - smallest and largest value cell a can take (its value if solved)
+fn value_bounds(cands: &[u64], values: &[u8], a: usize) -> (i64, i64) {
+    match (values[a], cands[a]) {
+        (0, 0) => (1 << 20, -(1 << 20)), // no candidates, so nothing fits
+        (0, mask) => (mask.trailing_zeros() as i64 + 1, 64 - mask.leading_zeros() as i64),
+        (v, _) => (v as i64, v as i64),
+    }
+}
+
+// extreme_sums - smallest and largest total of k different values from the states in
+// mask (a large gap if there aren't k of them)
+fn extreme_sums(mask: u64, k: usize) -> (i64, i64) {
+    if (mask.count_ones() as usize) < k {
+        return (i64::MAX, i64::MIN);
+    }
+    let (mut low, mut high) = (0, 0);
+    let (mut small, mut large) = (mask, mask);
+    for _ in 0..k {
+        low += small.trailing_zeros() as i64 + 1;
+        small &= small - 1;
+        high += 64 - large.leading_zeros() as i64;
+        large &= !(1u64 << (63 - large.leading_zeros()));
+    }
+    (low, high)
+}
+
+// all_of - the candidates of any of the cells
+fn all_of(cands: &[u64], cells: &[usize]) -> u64 {
+    cells.iter().fold(0, |mask, &a| mask | cands[a])
+}
+
+// cage_fill - try every way of filling the open cells of a cage from their candidates,
+// without repeats and (when there is one) making the target, noting in seen the states
+// each cell takes in some way that works
+fn cage_fill(open: &[usize], cands: &[u64], used: u64, target: Option<i64>, k: usize, chosen: &mut [u32],
+             seen: &mut [u64]) {
+    if k == open.len() {
+        if target.is_none_or(|t| t == 0) {
+            for (j, &s) in chosen.iter().enumerate() {
+                seen[j] |= 1u64 << s;
+            }
+        }
+        return;
+    }
+    let mut options = cands[open[k]] & !used;
+    while options != 0 {
+        let s = options.trailing_zeros();
+        options &= options - 1;
+        let left = target.map(|t| t - (s + 1) as i64);
+        if left.is_some_and(|t| t < 0) {
+            break; // states only get larger
+        }
+        // the cells after this one have to be able to make what is left
+        let (low, high) = extreme_sums(all_of(cands, &open[k + 1..]) & !used & !(1u64 << s), open.len() - k - 1);
+        if left.is_some_and(|t| t < low || t > high) {
+            continue;
+        }
+        chosen[k] = s;
+        cage_fill(open, cands, used | 1u64 << s, left, k + 1, chosen, seen);
+    }
+}
+
+// calc_seen - the states each cell of a calcudoku cage (on a grid of n states) takes in
+// some way of making target by op, trying every combination of the options of its cells
+// in which cells sharing a row or column differ
+fn calc_seen(op: CageOp, target: u64, cells: &[usize], options: &[u64], n: usize) -> Vec<u64> {
+    let mut seen = vec![0u64; cells.len()];
+    let mut chosen = vec![0u64; cells.len()];
+    calc_fill(op, target, cells, options, n, 0, &mut chosen, &mut seen);
+    seen
+}
+
+// calc_fill - choose the value of cell k of a calcudoku cage and on, noting in seen the
+// states of every complete choice making the target (sums and products are cut short once
+// they can no longer make it)
+#[allow(clippy::too_many_arguments)]
+fn calc_fill(op: CageOp, target: u64, cells: &[usize], options: &[u64], n: usize, k: usize, chosen: &mut [u64],
+             seen: &mut [u64]) {
+    if k == cells.len() {
+        if op.apply(chosen) == Some(target) {
+            for (j, &v) in chosen.iter().enumerate() {
+                seen[j] |= 1u64 << (v - 1);
+            }
+        }
+        return;
+    }
+    let (row, col) = (cells[k] / n, cells[k] % n);
+    let mut mask = options[k];
+    while mask != 0 {
+        let v = mask.trailing_zeros() as u64 + 1;
+        mask &= mask - 1;
+        let clash = (0..k).any(|j| chosen[j] == v && (cells[j] / n == row || cells[j] % n == col));
+        let partial = &chosen[..k];
+        let rest = &options[k + 1..];
+        let hopeless = match op {
+            CageOp::Add => {
+                let (low, high) = rest.iter().fold((0, 0), |(l, h), &m| {
+                    (l + m.trailing_zeros() as u64 + 1, h + 64 - m.leading_zeros() as u64)
+                });
+                let total = partial.iter().sum::<u64>() + v;
+                total + low > target || total + high < target
+            }
+            CageOp::Mul => {
+                let product = partial.iter().try_fold(v, |p, &w| p.checked_mul(w));
+                let high = rest.iter().try_fold(1u64, |p, &m| p.checked_mul(64 - m.leading_zeros() as u64));
+                product.is_none_or(|p| !target.is_multiple_of(p) || high.is_some_and(|h| p.saturating_mul(h) < target))
+            }
+            CageOp::Sub | CageOp::Div => false,
+        };
+        if clash || hopeless {
+            continue;
+        }
+        chosen[k] = v;
+        calc_fill(op, target, cells, options, n, k + 1, chosen, seen);
+    }
+}
+
+// line_options - the states each cell of a row or column can take from the values alone:
+// its own if solved (other than address), or any not used along the line
+fn line_options(cells: &[usize], values: &[u8], address: Option<usize>, n: usize) -> Vec<u64> {
+    let all = if n == 64 { u64::MAX } else { (1u64 << n) - 1 };
+    let solved = |a: usize| values[a] != 0 && Some(a) != address;
+    let used = cells.iter().filter(|&&a| solved(a)).fold(0u64, |m, &a| m | 1u64 << (values[a] - 1));
+    cells.iter().map(|&a| if solved(a) { 1u64 << (values[a] - 1) } else { all & !used }).collect()
+}
+
+// sky_bound - the states cell k along from a skyscraper clue of count can take at most:
+// the count-1 buildings seen after it need that many taller ones
+fn sky_bound(count: u32, k: usize, n: usize) -> u64 {
+    value_range(1, n as i64 - count as i64 + 1 + k as i64, n)
+}
+
+// sky_seen - the states each cell of a row or column (from the clue inwards) takes in
+// some way of filling it from options that sees count buildings, trying every one - None
+// if that would take more than LINE_BUDGET steps
+fn sky_seen(count: u32, options: &[u64], n: usize) -> Option<Vec<u64>> {
+    let mut seen = vec![0u64; options.len()];
+    let mut chosen = vec![0u32; options.len()];
+    let mut budget = LINE_BUDGET;
+    sky_fill(count, options, n, (0, 0, 0), &mut chosen, &mut seen, &mut budget).then_some(seen)
+}
+
+// sky_fill - choose the value of the next cell along (k, with the tallest so far and how
+// many have been seen, from at) and on, noting in seen the states of every complete choice
+// seeing count; false if the budget runs out first
+fn sky_fill(count: u32, options: &[u64], n: usize, at: (usize, u32, u32), chosen: &mut [u32], seen: &mut [u64],
+            budget: &mut usize) -> bool {
+    let (k, tallest, visible) = at;
+    if k == options.len() {
+        if visible == count {
+            for (j, &v) in chosen.iter().enumerate() {
+                seen[j] |= 1u64 << (v - 1);
+            }
+        }
+        return true;
+    }
+    if *budget == 0 {
+        return false;
+    }
+    *budget -= 1;
+    // the tallest left will be seen, and at most every taller one left
+    let all = if n == 64 { u64::MAX } else { (1u64 << n) - 1 };
+    let used = chosen[..k].iter().fold(0u64, |m, &v| m | 1u64 << (v - 1));
+    let taller = all & !used & u64::MAX.checked_shl(tallest).unwrap_or(0);
+    if count < visible + (taller != 0) as u32 || count > visible + taller.count_ones() {
+        return true;
+    }
+    let mut mask = options[k] & !used;
+    while mask != 0 {
+        let v = mask.trailing_zeros() + 1;
+        mask &= mask - 1;
+        chosen[k] = v;
+        let next = (k + 1, tallest.max(v), visible + (v > tallest) as u32);
+        if !sky_fill(count, options, n, next, chosen, seen, budget) {
+            return false;
+        }
+    }
+    true
+}
+
+// xsum_seen - the states each cell of a row or column (from the clue inwards) takes in
+// some way of filling it from options where the first cell's value of cells add up to
+// sum - the cells after those keep all their options
+fn xsum_seen(sum: u32, options: &[u64]) -> Vec<u64> {
+    let mut seen = vec![0u64; options.len()];
+    let mut firsts = options[0];
+    while firsts != 0 {
+        let x = firsts.trailing_zeros() as usize + 1;
+        firsts &= firsts - 1;
+        if x > options.len() || x as u32 > sum {
+            continue;
+        }
+        let open: Vec<usize> = (1..x).collect();
+        let mut inner = vec![0u64; open.len()];
+        let mut chosen = vec![0; open.len()];
+        cage_fill(&open, options, 1u64 << (x - 1), Some(sum as i64 - x as i64), 0, &mut chosen, &mut inner);
+        if if x == 1 { sum == 1 } else { inner[0] != 0 } {
+            seen[0] |= 1u64 << (x - 1);
+            for k in 1..x {
+                seen[k] |= inner[k - 1];
+            }
+            for k in x..options.len() {
+                seen[k] |= options[k];
+            }
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: u64 = 0x1ff;
+
+    // line - constraint from its line in a .sud file of 9 states
+    fn line(text: &str) -> Constraint {
+        Constraint::parse(text, 9).unwrap().unwrap()
+    }
+
+    // mask - bitmask of the states counting as the values given
+    fn mask(values: &[u8]) -> u64 {
+        values.iter().fold(0, |m, &v| m | 1u64 << (v - 1))
+    }
+
+    // values - values of a 9x9 grid with the cells given solved, e.g. ("r1c1", 5)
+    fn values(solved: &[(&str, u8)]) -> Vec<u8> {
+        let mut values = vec![0; 81];
+        for &(cell, v) in solved {
+            values[parse_cell(cell, 9).unwrap()] = v;
+        }
+        values
+    }
+
+    // kept - the candidates each cell of the constraint keeps after eliminate, the
+    // unsolved cells starting with all but those given
+    fn kept(c: &Constraint, values: &[u8], given: &[(&str, &[u8])]) -> Vec<u64> {
+        let mut cands: Vec<u64> = values.iter().map(|&v| if v == 0 { ALL } else { 0 }).collect();
+        for &(cell, states) in given {
+            cands[parse_cell(cell, 9).unwrap()] = mask(states);
+        }
+        for (a, s) in c.eliminate(&cands, values, 9) {
+            cands[a] &= !(1u64 << s);
+        }
+        c.cells().iter().map(|&a| cands[a]).collect()
+    }
+
+    #[test]
+    fn killer_cage_candidates() {
+        let empty = values(&[]);
+        assert_eq!(line("cage 3 r1c1 r1c2").allowed(0, &empty, 9), mask(&[1, 2]));
+        assert_eq!(line("cage 17 r1c1 r1c2").allowed(0, &empty, 9), mask(&[8, 9]));
+        // with the 9 placed the other two have to make 3, and no value repeats
+        let cage = line("cage 12 r1c1 r1c2 r1c3");
+        assert_eq!(cage.allowed(0, &values(&[("r1c2", 9)]), 9), mask(&[1, 2]));
+        assert_eq!(line("cage ? r1c1 r1c2").allowed(0, &values(&[("r1c2", 4)]), 9), ALL & !mask(&[4]));
+
+        assert_eq!(kept(&line("cage 6 r1c1 r1c2 r1c3"), &empty, &[]), vec![mask(&[1, 2, 3]); 3]);
+        assert_eq!(kept(&line("cage 10 r1c1 r1c2"), &empty, &[("r1c1", &[1, 2, 3])]),
+                   vec![mask(&[1, 2, 3]), mask(&[7, 8, 9])]);
+    }
+
+    #[test]
+    fn parity_and_only_candidates() {
+        let empty = values(&[]);
+        let odd = line("odd r1c1 r2c2");
+        let even = line("even r1c2");
+        let only = line("only 2,3,7 r1c3 r9c9");
+        assert_eq!(odd.allowed(0, &empty, 9), mask(&[1, 3, 5, 7, 9]));
+        assert_eq!(even.allowed(1, &empty, 9), mask(&[2, 4, 6, 8]));
+        assert_eq!(only.allowed(80, &empty, 9), mask(&[2, 3, 7]));
+        assert_eq!(Constraint::parse("odd r1c1", 4).unwrap().unwrap().allowed(0, &[0; 16], 4), mask(&[1, 3]));
+
+        assert_eq!(kept(&odd, &empty, &[("r1c1", &[1, 2, 3, 4])]), vec![mask(&[1, 3]), mask(&[1, 3, 5, 7, 9])]);
+        assert_eq!(kept(&only, &values(&[("r1c3", 2)]), &[("r9c9", &[1, 7, 8])]), vec![0, mask(&[7])]);
+
+        assert_eq!(odd.broken(&values(&[("r1c1", 3), ("r2c2", 4)]), 9), Some((vec![10], "isn't odd".to_owned())));
+        assert_eq!(even.broken(&values(&[("r1c2", 5)]), 9), Some((vec![1], "isn't even".to_owned())));
+        assert_eq!(only.broken(&values(&[("r1c3", 1)]), 9), Some((vec![2], "isn't one of 2,3,7".to_owned())));
+        assert_eq!(only.broken(&values(&[("r1c3", 3), ("r9c9", 7)]), 9), None);
+    }
+
+    #[test]
+    fn thermo_candidates() {
+        let thermo = line("thermo r1c1 r1c2 r1c3");
+        let empty = values(&[]);
+        assert_eq!(thermo.allowed(0, &empty, 9), mask(&[1, 2, 3, 4, 5, 6, 7]));
+        assert_eq!(thermo.allowed(1, &empty, 9), mask(&[2, 3, 4, 5, 6, 7, 8]));
+        assert_eq!(thermo.allowed(2, &empty, 9), mask(&[3, 4, 5, 6, 7, 8, 9]));
+        let middle = values(&[("r1c2", 5)]);
+        assert_eq!(thermo.allowed(0, &middle, 9), mask(&[1, 2, 3, 4]));
+        assert_eq!(thermo.allowed(2, &middle, 9), mask(&[6, 7, 8, 9]));
+
+        // the bulb at least 5 and the tip at most 7 leave one way
+        assert_eq!(kept(&thermo, &empty, &[("r1c1", &[5, 6]), ("r1c3", &[1, 2, 3, 4, 5, 6, 7])]),
+                   vec![mask(&[5]), mask(&[6]), mask(&[7])]);
+    }
+
+    #[test]
+    fn arrow_candidates() {
+        let arrow = line("arrow r1c1 r1c2 r1c3");
+        let empty = values(&[]);
+        assert_eq!(arrow.allowed(0, &empty, 9), ALL & !mask(&[1]));
+        assert_eq!(arrow.allowed(1, &empty, 9), ALL & !mask(&[9]));
+        assert_eq!(arrow.allowed(1, &values(&[("r1c1", 4)]), 9), mask(&[1, 2, 3]));
+
+        // a circle of 9 from two cells of at most 5 needs at least 4 in each
+        let given: &[(&str, &[u8])] = &[("r1c1", &[9]), ("r1c2", &[1, 2, 3, 4, 5]), ("r1c3", &[1, 2, 3, 4, 5])];
+        assert_eq!(kept(&arrow, &empty, given), vec![mask(&[9]), mask(&[4, 5]), mask(&[4, 5])]);
+    }
+
+    #[test]
+    fn sandwich_candidates() {
+        let sandwich = line("sandwich row 1 12");
+        // until both crusts are placed every value is allowed
+        assert!((0..9).all(|a| sandwich.allowed(a, &values(&[]), 9) == ALL));
+        let bread = values(&[("r1c1", 1)]);
+        assert_eq!(sandwich.allowed(4, &bread, 9), ALL);
+        // 1 and 9 with two cells between them making 12 (from 2..8)
+        let bread = values(&[("r1c1", 1), ("r1c4", 9)]);
+        assert_eq!(sandwich.allowed(1, &bread, 9), mask(&[4, 5, 6, 7, 8]));
+        assert_eq!(sandwich.allowed(8, &bread, 9), ALL);
+
+        // nothing between them: with the 1 at the end the 9 has to be next to it
+        let zero = line("sandwich row 1 0");
+        let cells = ["r1c2", "r1c3", "r1c4", "r1c5", "r1c6", "r1c7", "r1c8", "r1c9"];
+        let no_one: Vec<(&str, &[u8])> = cells.iter().map(|&c| (c, &[2, 3, 4, 5, 6, 7, 8, 9][..])).collect();
+        let kept_zero = kept(&zero, &values(&[("r1c1", 1)]), &no_one);
+        assert_eq!(kept_zero[1], mask(&[9]));
+        assert!(kept_zero[2..].iter().all(|&k| k == ALL & !mask(&[1, 9])));
+
+        // 5 between them is 5 or 2+3, so the cells between keep only 2, 3 and 5
+        let five = line("sandwich row 1 5");
+        let bread = values(&[("r1c1", 1), ("r1c4", 9)]);
+        let kept_five = kept(&five, &bread, &[]);
+        assert_eq!(&kept_five[1..3], [mask(&[2, 3]), mask(&[2, 3])]);
+        assert!(kept_five[4..].iter().all(|&k| k == ALL & !mask(&[1, 9])));
+        // with only the 1 placed, the next cell is between the crusts, and the one after
+        // is the 9 or with it makes 2+3
+        let kept_five = kept(&five, &values(&[("r1c1", 1)]), &[]);
+        assert_eq!(kept_five[1], mask(&[2, 3, 5]));
+        assert_eq!(kept_five[2], mask(&[2, 3, 9]));
+    }
+
+    #[test]
+    fn pairs_need_neighbouring_cells() {
+        assert!(Constraint::parse("kropki white r1c1 r1c2", 9).unwrap().is_ok());
+        assert!(Constraint::parse("xv v r2c1 r1c1", 9).unwrap().is_ok());
+        assert!(Constraint::parse("greater r1c9 r2c9", 9).unwrap().is_ok());
+        for text in ["kropki white r1c1 r1c3", "xv x r1c1 r2c2", "greater r1c9 r2c1", "greater r1c1 r1c1"] {
+            assert_eq!(Constraint::parse(text, 9), Some(Err(GridError::BadConstraint(text.to_owned()))));
+        }
+    }
+
+    #[test]
+    fn negative_leaves_out_dotted_pairs() {
+        let mut constraints = vec![line("negative kropki"), line("kropki white r1c1 r1c2"), line("xv x r2c1 r2c2")];
+        refresh_negatives(&mut constraints, 9);
+        let cells = constraints[0].cells();
+        assert_eq!(cells.len(), 2 * (2 * 9 * 8 - 1));
+        assert!(!cells.chunks(2).any(|pair| pair == [0, 1]));
+        assert!(cells.chunks(2).any(|pair| pair == [9, 10]));
+    }
+
+    // calc - allowed at each cell of a calcudoku cage on an empty 6x6 grid (with the cells
+    // given solved), and whether it is broken
+    fn calc(text: &str, solved: &[(usize, u8)]) -> (Vec<u64>, bool) {
+        let cage = Constraint::parse(text, 6).unwrap().unwrap();
+        let mut values = vec![0; 36];
+        for &(a, v) in solved {
+            values[a] = v;
+        }
+        let allowed = cage.cells().iter().map(|&a| cage.allowed(a, &values, 6)).collect();
+        assert_eq!(cage.allowed_all(&values, 6).map(|all| all.len()), Some(cage.cells().len()));
+        (allowed, cage.broken(&values, 6).is_some())
+    }
+
+    #[test]
+    fn calcudoku_cage_candidates() {
+        assert_eq!(calc("cage 3+ r1c1 r1c2", &[]), (vec![mask(&[1, 2]); 2], false));
+        assert_eq!(calc("cage 11+ r1c1 r1c2", &[]), (vec![mask(&[5, 6]); 2], false));
+        assert_eq!(calc("cage 5- r1c1 r2c1", &[]), (vec![mask(&[1, 6]); 2], false));
+        assert_eq!(calc("cage 4- r1c1 r2c1", &[]), (vec![mask(&[1, 2, 5, 6]); 2], false));
+        assert_eq!(calc("cage 12x r1c1 r1c2", &[]), (vec![mask(&[2, 3, 4, 6]); 2], false));
+        // the corner cells of an L may repeat: 16 is 4x1x4 or 2x4x2
+        assert_eq!(calc("cage 16x r1c1 r1c2 r2c2", &[]), (vec![mask(&[2, 4]), mask(&[1, 4]), mask(&[2, 4])], false));
+        assert_eq!(calc("cage 3/ r1c1 r1c2", &[]), (vec![mask(&[1, 2, 3, 6]); 2], false));
+        assert_eq!(calc("cage 3/ r1c1 r1c2", &[(1, 2)]).0[0], mask(&[6]));
+        assert_eq!(calc("cage 6+ r1c1", &[]), (vec![mask(&[6])], false));
+
+        // no two values up to 6 divide to make 7, nor to 4 with 3 in place
+        assert_eq!(calc("cage 7÷ r1c1 r1c2", &[]), (vec![0; 2], false));
+        assert!(calc("cage 7÷ r1c1 r1c2", &[(0, 5), (1, 3)]).1);
+        assert_eq!(calc("cage 4/ r1c1 r1c2", &[(0, 3)]), (vec![mask(&[1, 4]), 0], true));
+        assert!(!calc("cage 4/ r1c1 r1c2", &[(0, 4)]).1);
+        assert!(calc("cage 2- r1c1 r1c2", &[(0, 3), (1, 4)]).1);
+    }
+
+    #[test]
+    fn skyscraper_counts() {
+        let empty = vec![0; 16];
+        let four = |text: &str| Constraint::parse(text, 4).unwrap().unwrap().allowed_all(&empty, 4).unwrap();
+        assert_eq!(four("skyscraper r1c0 4"), vec![mask(&[1]), mask(&[2]), mask(&[3]), mask(&[4])]);
+        assert_eq!(four("skyscraper r0c2 1"), vec![mask(&[4]), mask(&[1, 2, 3]), mask(&[1, 2, 3]), mask(&[1, 2, 3])]);
+        // seen from the right: 3 and 4 can't be first, and 4 isn't second
+        assert_eq!(four("skyscraper r1c5 3"), vec![mask(&[1, 2]), mask(&[1, 2, 3]), mask(&[1, 2, 3, 4]), mask(&[1, 2, 3, 4])]);
+
+        let sky = line("skyscraper r0c1 2");
+        let seen = values(&[("r1c1", 9)]);
+        assert!(sky.broken(&seen, 9).is_some());
+        assert_eq!(sky.allowed(9, &values(&[("r1c1", 8)]), 9), mask(&[1, 2, 3, 4, 5, 6, 7, 9]));
+        assert!(sky.broken(&values(&[("r1c1", 8), ("r2c1", 9)]), 9).is_none());
+    }
+
+    #[test]
+    fn skyscraper_falls_back_to_bounds_past_the_budget() {
+        // every way of filling a 16 cell line is far more than LINE_BUDGET steps
+        let all = (1u64 << 16) - 1;
+        assert_eq!(sky_seen(8, &[all; 16], 16), None);
+        assert!(sky_seen(2, &[mask(&[1, 2, 3, 4]); 4], 4).is_some());
+
+        let sky = Constraint::parse("skyscraper r0c1 8", 16).unwrap().unwrap();
+        let bounds: Vec<u64> = (0..16).map(|k| all & sky_bound(8, k, 16)).collect();
+        assert_eq!(bounds[0], (1u64 << 9) - 1);
+        assert_eq!(sky.allowed_all(&[0; 256], 16), Some(bounds.clone()));
+        assert_eq!(sky.allowed(0, &[0; 256], 16), bounds[0]);
+    }
+
+    #[test]
+    fn xsum_candidates() {
+        // 2+8, 3+1+6 or 3+2+5, 4+1+2+3
+        let seen = line("xsum r1c0 10").allowed_all(&values(&[]), 9).unwrap();
+        assert_eq!(seen[0], mask(&[2, 3, 4]));
+        assert_eq!(seen[1], mask(&[1, 2, 3, 5, 6, 8]));
+        assert_eq!(seen[4], ALL);
+        // counted from the bottom of column 1, with a 3 placed there
+        let xsum = line("xsum r10c1 10");
+        assert_eq!(xsum.allowed(63, &values(&[("r9c1", 3)]), 9), mask(&[1, 2, 5, 6]));
+        assert!(xsum.broken(&values(&[("r9c1", 3), ("r8c1", 4)]), 9).is_some());
+        assert!(xsum.broken(&values(&[("r9c1", 2), ("r8c1", 8)]), 9).is_none());
+    }
+
+    #[test]
+    fn little_killer_candidates() {
+        let diagonal = line("littlekiller r0c7 se 6");
+        assert_eq!(diagonal.cells(), &[7, 17]);
+        assert_eq!(diagonal.allowed(7, &values(&[]), 9), mask(&[1, 2, 3, 4, 5]));
+        assert_eq!(diagonal.allowed(7, &values(&[("r2c9", 2)]), 9), mask(&[4]));
+        assert!(diagonal.broken(&values(&[("r1c8", 5), ("r2c9", 3)]), 9).is_some());
+
+        // three cells making 24, so none less than 24-9-9, and with a 9 at the end the
+        // other two make 15
+        let long = line("littlekiller r10c6 ne 24");
+        assert_eq!(long.cells(), &[78, 70, 62]);
+        assert_eq!(kept(&long, &values(&[]), &[]), vec![mask(&[6, 7, 8, 9]); 3]);
+        assert_eq!(kept(&long, &values(&[("r7c9", 9)]), &[("r9c7", &[5, 6, 7])]), vec![mask(&[6, 7]), mask(&[8, 9]), 0]);
+        assert!(Constraint::parse("littlekiller r0c8 se 5", 9).unwrap().is_err()); // one cell
+    }
+
+    #[test]
+    fn broken_catches_each_break() {
+        let cage = line("cage 10 r1c1 r1c2 r1c3");
+        assert_eq!(cage.broken(&values(&[("r1c1", 3), ("r1c3", 3)]), 9), Some((vec![0, 2], "repeats 3".to_owned())));
+        assert_eq!(cage.broken(&values(&[("r1c1", 1), ("r1c2", 2), ("r1c3", 4)]), 9),
+                   Some((vec![0, 1, 2], "adds up to 7 rather than 10".to_owned())));
+        assert_eq!(cage.broken(&values(&[("r1c1", 9), ("r1c2", 8)]), 9),
+                   Some((vec![0, 1, 2], "can't add up to 10".to_owned())));
+        assert_eq!(cage.broken(&values(&[("r1c1", 1), ("r1c2", 2)]), 9), None);
+
+        let thermo = line("thermo r1c1 r1c2 r1c3");
+        assert!(thermo.broken(&values(&[("r1c1", 5), ("r1c2", 3)]), 9).is_some());
+        assert!(thermo.broken(&values(&[("r1c1", 8)]), 9).is_some()); // no room above it
+        assert_eq!(thermo.broken(&values(&[("r1c1", 3), ("r1c3", 5)]), 9), None);
+
+        let arrow = line("arrow r1c1 r1c2 r1c3");
+        assert!(arrow.broken(&values(&[("r1c1", 3), ("r1c2", 4)]), 9).is_some());
+        assert!(arrow.broken(&values(&[("r1c1", 7), ("r1c2", 2), ("r1c3", 4)]), 9).is_some());
+        assert_eq!(arrow.broken(&values(&[("r1c1", 7), ("r1c2", 2)]), 9), None);
+
+        let sandwich = line("sandwich row 1 12");
+        assert!(sandwich.broken(&values(&[("r1c1", 1), ("r1c2", 2), ("r1c3", 3), ("r1c4", 9)]), 9).is_some());
+        assert!(sandwich.broken(&values(&[("r1c1", 1), ("r1c2", 9)]), 9).is_some());
+        assert_eq!(sandwich.broken(&values(&[("r1c1", 1), ("r1c2", 5), ("r1c3", 7), ("r1c4", 9)]), 9), None);
+    }
+}
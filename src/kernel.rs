@@ -0,0 +1,176 @@
+// Unit-scan kernels
+// a unit scan ORs together the state masks of the cells of a unit (row, column or
+// block), giving the states already used there - it is the inner loop of updating
+// candidates, so has architecture specific versions behind cfg(target_arch), with the
+// scalar version everywhere as the fallback
+// the kernel is chosen at run time: the best one this cpu supports, unless selected
+// (this is also where an ARM assembly version, header goal 9, would be added)
+
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::GridError;
+
+/// Kernel is an implementation of the unit scan
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Kernel {
+    Scalar, // plain Rust, on every platform
+    Neon,   // aarch64 NEON, 2 masks at a time
+    Sse2,   // x86_64 SSE2, 2 masks at a time
+    Avx2,   // x86_64 AVX2, 4 masks at a time
+}
+
+/// Every kernel, in order of preference (best last)
+pub const KERNELS: [Kernel; 4] = [Kernel::Scalar, Kernel::Neon, Kernel::Sse2, Kernel::Avx2];
+
+// selected kernel, as its position in KERNELS (AUTO until one is selected)
+const AUTO: u8 = u8::MAX;
+static SELECTED: AtomicU8 = AtomicU8::new(AUTO);
+
+impl Kernel {
+    /// name - short lowercase name, as used by --kernel
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kernel::Scalar => "scalar",
+            Kernel::Neon => "neon",
+            Kernel::Sse2 => "sse2",
+            Kernel::Avx2 => "avx2",
+        }
+    }
+
+    /// from_name - kernel with the given name
+    pub fn from_name(name: &str) -> Option<Kernel> {
+        KERNELS.into_iter().find(|k| k.name() == name)
+    }
+
+    /// available - whether the kernel is built for this platform and the cpu supports it
+    pub fn available(&self) -> bool {
+        match self {
+            Kernel::Scalar => true,
+            #[cfg(target_arch = "aarch64")]
+            Kernel::Neon => std::arch::is_aarch64_feature_detected!("neon"),
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Sse2 => true, // part of the x86_64 baseline
+            #[cfg(target_arch = "x86_64")]
+            Kernel::Avx2 => std::arch::is_x86_feature_detected!("avx2"),
+            #[allow(unreachable_patterns)]
+            _ => false,
+        }
+    }
+
+    /// best - the most capable kernel available
+    pub fn best() -> Kernel {
+        KERNELS.into_iter().rev().find(|k| k.available()).unwrap_or(Kernel::Scalar)
+    }
+
+    /// current - the selected kernel, otherwise the best
+    pub fn current() -> Kernel {
+        match SELECTED.load(Ordering::Relaxed) {
+            AUTO => Kernel::best(),
+            k => KERNELS[k as usize],
+        }
+    }
+
+    /// select - use this kernel for every unit scan from now on
+    pub fn select(self) -> Result<(), GridError> {
+        if !self.available() {
+            return Err(GridError::Unsupported(self.name()));
+        }
+        let position = KERNELS.iter().position(|&k| k == self).unwrap_or(0);
+        SELECTED.store(position as u8, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// unit_scan - OR of the masks, using the current kernel
+pub fn unit_scan(masks: &[u64]) -> u64 {
+    scan_with(Kernel::current(), masks)
+}
+
+// scan_with - OR of the masks using the given kernel, which has to be available
+// (scalar if it isn't built here)
+fn scan_with(kernel: Kernel, masks: &[u64]) -> u64 {
+    match kernel {
+        #[cfg(target_arch = "aarch64")]
+        // SAFETY: callers only pass kernels that available() has checked for neon
+        Kernel::Neon => unsafe { scan_neon(masks) },
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: sse2 is part of the x86_64 baseline
+        Kernel::Sse2 => unsafe { scan_sse2(masks) },
+        #[cfg(target_arch = "x86_64")]
+        // SAFETY: callers only pass kernels that available() has checked for avx2
+        Kernel::Avx2 => unsafe { scan_avx2(masks) },
+        _ => scan_scalar(masks),
+    }
+}
+
+// scan_scalar - unit scan one mask at a time
+fn scan_scalar(masks: &[u64]) -> u64 {
+    masks.iter().fold(0, |used, &mask| used | mask)
+}
+
+// scan_neon - unit scan 2 masks at a time
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn scan_neon(masks: &[u64]) -> u64 {
+    use std::arch::aarch64::*;
+
+    let pairs = masks.chunks_exact(2);
+    let rest = scan_scalar(pairs.remainder());
+    let mut used = vdupq_n_u64(0);
+    for pair in pairs {
+        // SAFETY: pair holds 2 u64s, and vld1q_u64 has no alignment requirement
+        used = vorrq_u64(used, unsafe { vld1q_u64(pair.as_ptr()) });
+    }
+    vgetq_lane_u64::<0>(used) | vgetq_lane_u64::<1>(used) | rest
+}
+
+// scan_sse2 - unit scan 2 masks at a time
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse2")]
+unsafe fn scan_sse2(masks: &[u64]) -> u64 {
+    use std::arch::x86_64::*;
+
+    let pairs = masks.chunks_exact(2);
+    let rest = scan_scalar(pairs.remainder());
+    let mut used = _mm_setzero_si128();
+    for pair in pairs {
+        // SAFETY: pair holds 16 bytes, and _mm_loadu_si128 has no alignment requirement
+        used = _mm_or_si128(used, unsafe { _mm_loadu_si128(pair.as_ptr() as *const __m128i) });
+    }
+    let high = _mm_unpackhi_epi64(used, used);
+    (_mm_cvtsi128_si64(used) | _mm_cvtsi128_si64(high)) as u64 | rest
+}
+
+// scan_avx2 - unit scan 4 masks at a time
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn scan_avx2(masks: &[u64]) -> u64 {
+    use std::arch::x86_64::*;
+
+    let quads = masks.chunks_exact(4);
+    let rest = scan_scalar(quads.remainder());
+    let mut used = _mm256_setzero_si256();
+    for quad in quads {
+        // SAFETY: quad holds 32 bytes, and _mm256_loadu_si256 has no alignment requirement
+        used = _mm256_or_si256(used, unsafe { _mm256_loadu_si256(quad.as_ptr() as *const __m256i) });
+    }
+    let half = _mm_or_si128(_mm256_castsi256_si128(used), _mm256_extracti128_si256::<1>(used));
+    let high = _mm_unpackhi_epi64(half, half);
+    (_mm_cvtsi128_si64(half) | _mm_cvtsi128_si64(high)) as u64 | rest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernels_agree_with_scalar() {
+        // one bit per mask, spread across the word, so a lost mask shows
+        let masks: Vec<u64> = (0..9).map(|k| 1u64 << (k * 7) | 1u64 << 63 >> k).collect();
+        for kernel in KERNELS.into_iter().filter(|k| k.available()) {
+            for len in 0..=masks.len() {
+                assert_eq!(scan_with(kernel, &masks[..len]), scan_scalar(&masks[..len]), "{} on {}", kernel.name(), len);
+            }
+        }
+    }
+}
@@ -0,0 +1,3320 @@
+//! Sudoku solver, with Hexdoku capability (grids of 1 to 64 states).
+//!
+//! A [`Grid`] is built with [`Grid::new`], or read with [`Grid::from_sud`] / [`Grid::from_line`].
+//! It can then be validated, stepped through logically ([`Grid::next_step`], [`Grid::rate`]),
+//! solved by brute force ([`Grid::solve`]), rendered ([`Grid::print`], [`Grid::to_sud`],
+//! [`Grid::to_json`]), or made with [`Grid::generate`].
+//! Extra rules on groups of cells, such as killer cages and thermometers, are a
+//! [`constraint::Constraint`].
+//! Overlapping grids such as Samurai, and the faces of a cube, are a [`poly::Polydoku`].
+//! Anything that can fail returns a [`GridError`].
+//!
+//! ```
+//! let line = ".1.2.3.1.......3";
+//! let mut g = sudoku::Grid::from_line(line, "example").unwrap();
+//! assert_eq!(g.solve().unwrap(), 1);
+//! ```
+
+// Sudoku solver
+// with Hexdoku capability
+// Jon Knox, 2025-11
+
+// Goals
+// 1. Define structures/implementations successfully, print name of cell
+// 2. Be able to print the grid by fmt:Display and fmt:Debug
+// 3. Apply functions to grid for cell status updates...
+//    a) tritri application of not-allowed
+//    b) column application of not-allowed
+//    c) row application of not-allowed
+// 5. Have CLI loop which has 'read', 'tritri', 'column', 'row' and 'show' functions
+// 6. IO stuff
+//    a) settle on file format, write test files
+//    b) implement a 'write' function
+//    c) implement a 'read' function
+// 7. Perform 'next' = one-step fill in a next "obvious" solution and display with terminal highlight
+// 8. Detect dependent pair in tri-tri
+// 9. Implement cell status updates in ARM assembly language
+// 10. Implement heuristic function for solving
+//    a) single fn thread from next free cell
+//    b) implement multiple threads (on M4 cpu)
+//    c) select best-case starting point
+// 11. Change dimensions from 9x9 of 3x3 3x3s of 1..9 to 16x16 of 4x4 of 4x4 of 0..9,a..f
+
+// Notes
+// vector alternatives described here:
+// Re: https://play.rust-lang.org/?version=nightly&mode=debug&edition=2018&gist=174c2ddb88ce053af6206927890d3591
+//
+
+// Imports
+use std::fmt;
+use std::sync::Arc;
+//use std::error::Error;
+//use colored_text::Colorize;
+// Re: https://github.com/seapagan/colored_text/blob/main/examples/basic.rs
+
+use colored::Colorize;
+
+pub mod constraint;
+pub mod kernel;
+pub mod poly;
+
+use constraint::{Constraint, PairRule};
+
+// sudoku number
+pub type Snumb = u8; // holds values 1..9 or 0 for unknown
+
+/// cell is a single element that holds a solution number (snumb)
+// uses value 0 if unsolved
+// the disallowed vector is an array [1..9] of known disallowed values, as ruled out by
+// logical steps - restrictions from the puzzle itself (odd/even cells, given candidates)
+// are constraints, so they are kept when clear_rc forgets the steps
+#[derive(Clone)]
+pub struct Cell {
+    pub solved: bool,    // whether the cell is solved
+    pub given: bool,     // whether the solution was given by the puzzle (rather than found)
+    pub solution: Snumb, // solved value of cell (only when self.solved==true)
+    pub possible: Vec<bool>,
+    pub disallowed: Vec<bool>,
+    pub highlight: u8,
+}
+
+/// Status of a Grid
+#[derive(Clone, Debug, PartialEq)]
+pub enum GridStatus {
+    Solved,     // All cells are complete and logically correct
+    Incomplete, // Puzzle is incomplete, number of cells remaining unsolved (include empty)
+    Invalid,    // There are logic errors
+    Unsolvable, // Nonspecific - has multiple solutions
+    Empty,      // Empty grid, ready to load
+    NotSquare,  // States count can't be divided into boxes
+}
+
+/// Unit is a row, column, block or jigsaw region of the grid, numbered from 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    Row(usize),
+    Col(usize),
+    Block(usize),
+    Region(usize),   // jigsaw region, numbered in reading order of first cell
+    Diagonal(usize), // 0 the leading diagonal, 1 the other
+    Window(usize),   // extra hyper (windoku) box, in reading order
+    Knight(usize),     // two cells a knight's move apart, by the address of the first
+    King(usize),       // two cells a king's move apart (diagonally), likewise
+    Neighbours(usize), // two cells side by side (non-consecutive), likewise
+}
+
+/// Units is the table of units of a grid - every unit as its cell addresses, and for
+/// every cell the units holding it and its peers (the other cells sharing a unit, or a
+/// chess move under anti-knight and anti-king) - worked out once, so that every check
+/// runs over the same table
+#[derive(Debug)]
+pub struct Units {
+    pub kinds: Vec<Unit>,         // what each unit is: rows, columns, blocks/regions, then extras
+    pub cells: Vec<Vec<usize>>,   // cell addresses of each unit, in reading order
+    pub of_cell: Vec<Vec<usize>>, // units holding each cell (positions in kinds)
+    pub peers: Vec<Vec<usize>>,   // other cells sharing a unit with each cell, in reading order
+    pub moves: Vec<Vec<usize>>,   // peers a chess move away sharing no unit with each cell
+    pub neighbours: Vec<Vec<usize>>, // cells beside each cell that can't hold a consecutive state
+}
+
+/// Conflict is a state repeated within a unit or a chess move, or (for neighbours)
+/// consecutive states side by side, as found by validate
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict {
+    pub unit: Unit,        // row, column or block, with its index
+    pub state: Snumb,      // the repeated state (the lower one for neighbours)
+    pub cells: Vec<usize>, // address of every cell in the unit holding it, in reading order
+}
+
+/// Violation is a constraint broken by the solved cells, as found by validate
+#[derive(Clone, Debug, PartialEq)]
+pub struct Violation {
+    pub constraint: usize, // position in the grid's constraints
+    pub cells: Vec<usize>, // address of every cell to blame, in reading order
+    pub reason: String,    // what is wrong, e.g. "adds up to 17 rather than 15"
+}
+
+/// GridError is what can go wrong building, loading, changing or solving a grid
+/// (rows and columns are numbered from 0, as for claim_rc)
+#[derive(Clone, Debug, PartialEq)]
+pub enum GridError {
+    AlreadySolved { row: usize, col: usize },           // claim on a cell already solved
+    Contradiction { unit: Unit, state: char },          // state repeated within a unit
+    WrongLength { expected: usize, got: usize },        // number of cells supplied
+    BadSymbol { symbol: char, row: usize, col: usize }, // not one of the states
+    NotSquare { states: usize },                        // number of states has no box shape
+    BadBoxes { states: usize, width: usize, height: usize }, // box shape doesn't tile the grid
+    Empty,                                              // nothing to read
+    NotUnique { solutions: usize },                     // needs exactly one solution (2 = more)
+    TooLarge { states: usize, max: usize },             // beyond what the operation handles
+    BadPermutation(&'static str),                       // what was not a permutation
+    Io { filename: String, message: String },           // unable to read or write a file
+    Unsupported(&'static str),                          // feature missing on this machine
+    NotForLayout(&'static str),                         // operation this kind of grid can't do
+    RegionSize { region: usize, cells: usize, expected: usize }, // jigsaw region of wrong size
+    RegionSplit { region: usize },                      // jigsaw region not all connected
+    UnknownVariant(String),                             // config token +name not a Variant
+    UnknownShape(String),                               // polydoku shape not named or positions
+    BadConstraint(String),                              // constraint line that can't be read
+    BadConfig(String),                                  // config line token that can't be read
+    PolydokuShape(String),                              // config line of a polydoku, by its shape
+    Overlap { row: usize, col: usize },                 // cell in more than one cage
+    EdgeMismatch(String),                               // cube edge cell the faces give differently, by name
+    Unsatisfied(String),                                // constraint the solved cells break
+}
+
+/// grid consists of 9x9 cells
+#[derive(Clone)]
+pub struct Grid {
+    pub name: String,       // e.g. "Dummy Sudoku"
+    pub state_dict: String, // e.g. "123456789"
+    pub status: GridStatus,
+    pub states: u8,  // states is also width is also height
+    pub isqrt: u8,   // integer sq root of states count
+    pub box_w: u8,   // columns per box (= isqrt for square boxes)
+    pub box_h: u8,   // rows per box
+    pub size: usize, // number of cells = states^2
+    pub cells: Vec<Cell>,
+    pub symbols: Vec<char>,
+    pub regions: Vec<usize>, // jigsaw region of each cell (empty when there are boxes)
+    pub variants: Vec<Variant>, // extra rules, as declared in the config line
+    pub constraints: Vec<Constraint>, // rules on particular cells, such as killer cages
+    pub units: Arc<Units>,   // rows, columns and blocks/regions (shared between clones)
+}
+
+/// Variant is an extra rule on top of rows, columns and boxes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    Diagonals, // each main diagonal holds every state (Sudoku-X)
+    Hyper,     // so do the extra boxes between the boxes (Windoku)
+    AntiKnight,     // no state repeated a knight's move apart
+    AntiKing,       // nor a king's move apart
+    NonConsecutive, // no consecutive states side by side
+    Toroidal,       // the edges wrap round, for jigsaw regions and the rules above
+}
+
+pub const VARIANTS: [Variant; 6] = [Variant::Diagonals, Variant::Hyper, Variant::AntiKnight, Variant::AntiKing,
+    Variant::NonConsecutive, Variant::Toroidal];
+
+/// Logical solving techniques, easiest first
+#[derive(Clone, Copy, PartialEq)]
+pub enum Technique {
+    FullHouse,        // last free cell of a row/column/block
+    NakedSingle,      // cell with only one candidate left
+    HiddenSingle,     // state with only one place left in a row/column/block
+    InniesOuties,     // cell whose value a unit's total leaves after the cages in or over it
+    CageSum,          // candidates no combination making a cage's sum uses
+    CageArithmetic,   // likewise for a calcudoku cage's target and operation
+    Thermometer,      // candidates too low or high for their place along a thermometer
+    ArrowSum,         // candidates that can't make (or be made by) an arrow's sum
+    SandwichSum,      // lowest and highest states where the sum can't fit between them
+    Skyscraper,       // candidates no way of filling a row or column to see its clue uses
+    XSum,             // candidates no way of making an X-sum from the first cells uses
+    LittleKiller,     // candidates too low or high for a diagonal's sum
+    Dots,             // candidates that can't meet a kropki or XV dot (or the lack of one)
+    GreaterThan,      // candidates too low or high for an inequality sign
+    LockedCandidates, // state confined to where a block meets a row/column
+    NakedPair,        // two cells of a unit with the same two candidates
+    HiddenPair,       // two states confined to the same two cells of a unit
+    NakedTriple,      // three cells of a unit with only three candidates between them
+    XWing,            // state confined to the same two columns of two rows (or vice versa)
+}
+
+pub const TECHNIQUES: [Technique; 19] = [
+    Technique::FullHouse,
+    Technique::NakedSingle,
+    Technique::HiddenSingle,
+    Technique::InniesOuties,
+    Technique::CageSum,
+    Technique::CageArithmetic,
+    Technique::Thermometer,
+    Technique::ArrowSum,
+    Technique::SandwichSum,
+    Technique::Skyscraper,
+    Technique::XSum,
+    Technique::LittleKiller,
+    Technique::Dots,
+    Technique::GreaterThan,
+    Technique::LockedCandidates,
+    Technique::NakedPair,
+    Technique::HiddenPair,
+    Technique::NakedTriple,
+    Technique::XWing,
+];
+
+/// Step is one logical deduction - cells claimed and/or candidates eliminated
+pub struct Step {
+    pub technique: Technique,
+    pub placed: Vec<(usize, Snumb)>,     // (address, state) claimed
+    pub eliminated: Vec<(usize, Snumb)>, // (address, state) no longer possible
+    pub reason: String,                  // explanation for the user
+}
+
+/// Rating of a puzzle from its logical solve
+pub struct Rating {
+    pub hardest: Option<Technique>, // hardest technique needed (None if nothing to do)
+    pub steps: usize,               // number of logical steps taken
+    pub score: u32,                 // total weight of the techniques used
+    pub solved: bool,               // whether logic alone completes the grid
+    pub uses: Vec<usize>,           // number of steps by each technique, in TECHNIQUES order
+}
+
+// Implement display trait
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // calculate solved cell count
+        let mut used: u32 = 0;
+        let total: usize = self.cells.len();
+        for i in 0..total {
+            if self.cells[i].solved {
+                used += 1;
+            }
+        }
+        write!(f, "{}: {}/{}", self.name, used, total)
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.kind(), self.index() + 1)
+    }
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GridError::AlreadySolved { row, col } => write!(f, "r{}c{} is already solved", row + 1, col + 1),
+            GridError::Contradiction { unit, state } => write!(f, "'{}' is repeated in {}", state, unit),
+            GridError::WrongLength { expected, got } => write!(f, "expected {} cells but found {}", expected, got),
+            GridError::BadSymbol { symbol, row, col } => {
+                write!(f, "'{}' at r{}c{} is not one of the states", symbol, row + 1, col + 1)
+            }
+            GridError::NotSquare { states } => write!(f, "{} states can't be divided into boxes", states),
+            GridError::BadBoxes { states, width, height } => {
+                write!(f, "{}x{} boxes don't make a grid of {} states", width, height, states)
+            }
+            GridError::Empty => write!(f, "nothing to read"),
+            GridError::NotUnique { solutions: 0 } => write!(f, "grid has no solution"),
+            GridError::NotUnique { .. } => write!(f, "grid has more than one solution"),
+            GridError::TooLarge { states, max } => {
+                write!(f, "{}x{} is larger than the {}x{} supported", states, states, max, max)
+            }
+            GridError::BadPermutation(what) => write!(f, "{} is not a permutation", what),
+            GridError::Io { filename, message } => write!(f, "{}: {}", filename, message),
+            GridError::Unsupported(what) => write!(f, "{} is not supported on this machine", what),
+            GridError::NotForLayout(what) => write!(f, "{} is not available for this grid layout", what),
+            GridError::RegionSize { region, cells, expected } => {
+                write!(f, "region {} has {} cells rather than {}", region + 1, cells, expected)
+            }
+            GridError::RegionSplit { region } => write!(f, "region {} is not all connected", region + 1),
+            GridError::UnknownVariant(name) => write!(f, "unknown variant +{}", name),
+            GridError::UnknownShape(shape) => write!(f, "unknown polydoku shape '{}'", shape),
+            GridError::BadConstraint(line) => write!(f, "can't read constraint '{}'", line),
+            GridError::BadConfig(token) => write!(f, "can't read '{}' in the config line", token),
+            GridError::PolydokuShape(shape) => write!(f, "'{}' is a polydoku shape, not a single grid", shape),
+            GridError::Overlap { row, col } => write!(f, "r{}c{} is in more than one cage", row + 1, col + 1),
+            GridError::EdgeMismatch(cell) => write!(f, "{} is given differently by the faces sharing it", cell),
+            GridError::Unsatisfied(what) => write!(f, "{} can't be satisfied", what),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hardest = self.hardest.map_or("none", |t| t.name());
+        write!(f, "{} - hardest technique {}, {} steps, score {}", self.grade(), hardest, self.steps, self.score)?;
+        if !self.solved {
+            write!(f, " (needs search to finish)")?;
+        }
+        Ok(())
+    }
+}
+
+// Implement associated functions
+
+impl Technique {
+    /// name - name of technique as used on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Technique::FullHouse => "full-house",
+            Technique::NakedSingle => "naked-single",
+            Technique::HiddenSingle => "hidden-single",
+            Technique::InniesOuties => "innies-outies",
+            Technique::CageSum => "cage-sum",
+            Technique::CageArithmetic => "cage-arithmetic",
+            Technique::Thermometer => "thermometer",
+            Technique::ArrowSum => "arrow-sum",
+            Technique::SandwichSum => "sandwich-sum",
+            Technique::Skyscraper => "skyscraper",
+            Technique::XSum => "x-sum",
+            Technique::LittleKiller => "little-killer",
+            Technique::Dots => "dots",
+            Technique::GreaterThan => "greater-than",
+            Technique::LockedCandidates => "locked-candidates",
+            Technique::NakedPair => "naked-pair",
+            Technique::HiddenPair => "hidden-pair",
+            Technique::NakedTriple => "naked-triple",
+            Technique::XWing => "x-wing",
+        }
+    }
+
+    /// from_name - look up a technique by name
+    pub fn from_name(name: &str) -> Option<Technique> {
+        TECHNIQUES.iter().copied().find(|t| t.name() == name)
+    }
+
+    /// weight - difficulty score for one use of the technique
+    pub fn weight(&self) -> u32 {
+        match self {
+            Technique::FullHouse => 1,
+            Technique::NakedSingle => 1,
+            Technique::HiddenSingle => 2,
+            Technique::InniesOuties => 3,
+            Technique::CageSum => 3,
+            Technique::CageArithmetic => 3,
+            Technique::Thermometer => 3,
+            Technique::ArrowSum => 3,
+            Technique::SandwichSum => 4,
+            Technique::Skyscraper => 4,
+            Technique::XSum => 4,
+            Technique::LittleKiller => 3,
+            Technique::Dots => 3,
+            Technique::GreaterThan => 2,
+            Technique::LockedCandidates => 4,
+            Technique::NakedPair => 5,
+            Technique::HiddenPair => 6,
+            Technique::NakedTriple => 7,
+            Technique::XWing => 8,
+        }
+    }
+}
+
+impl Unit {
+    /// kind - "row", "column" or "block"
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Unit::Row(_) => "row",
+            Unit::Col(_) => "column",
+            Unit::Block(_) => "block",
+            Unit::Region(_) => "region",
+            Unit::Diagonal(_) => "diagonal",
+            Unit::Window(_) => "window",
+            Unit::Knight(_) => "knight's move",
+            Unit::King(_) => "king's move",
+            Unit::Neighbours(_) => "neighbours",
+        }
+    }
+
+    /// index - position of the unit among those of its kind, from 0
+    pub fn index(&self) -> usize {
+        match *self {
+            Unit::Row(i) | Unit::Col(i) | Unit::Block(i) | Unit::Region(i) | Unit::Diagonal(i) | Unit::Window(i)
+            | Unit::Knight(i) | Unit::King(i) | Unit::Neighbours(i) => i,
+        }
+    }
+}
+
+impl Units {
+    /// standard - rows, then columns, then blocks bw wide and bh high of a grid of n states
+    /// (no blocks unless bw*bh is n)
+    pub fn standard(n: usize, bw: usize, bh: usize) -> Units {
+        let mut units = lines(n);
+        units.extend(blocks(n, bw, bh));
+        Units::from_units(n * n, units)
+    }
+
+    /// jigsaw - rows, then columns, then the regions of a grid of n states, given the
+    /// region of each cell (numbered from 0)
+    pub fn jigsaw(n: usize, regions: &[usize]) -> Units {
+        let mut units = lines(n);
+        units.extend(jigsaw_regions(n, regions));
+        Units::from_units(n * n, units)
+    }
+
+    /// from_units - table for a grid of size cells from its units
+    pub fn from_units(size: usize, units: Vec<(Unit, Vec<usize>)>) -> Units {
+        let (kinds, cells): (Vec<Unit>, Vec<Vec<usize>>) = units.into_iter().unzip();
+        let mut of_cell = vec![Vec::new(); size];
+        for (u, unit) in cells.iter().enumerate() {
+            for &address in unit {
+                of_cell[address].push(u);
+            }
+        }
+        let peers = (0..size).map(|address| {
+            let mut peers: Vec<usize> = of_cell[address].iter()
+                .flat_map(|&u| cells[u].iter().copied())
+                .filter(|&a| a != address)
+                .collect();
+            peers.sort_unstable();
+            peers.dedup();
+            peers
+        }).collect();
+        Units { kinds, cells, of_cell, peers, moves: vec![Vec::new(); size], neighbours: vec![Vec::new(); size] }
+    }
+
+    /// add_moves - make cells a step (row and column offset, either sign) apart on a grid
+    /// of n states peers too, as for anti-knight - those sharing a unit already are left out
+    /// (wrap for steps off one edge to come back on the other)
+    pub fn add_moves(&mut self, n: usize, steps: &[(usize, usize)], wrap: bool) {
+        for address in 0..n * n {
+            for other in offsets(n, address, steps, wrap) {
+                if !self.of_cell[address].iter().any(|u| self.of_cell[other].contains(u))
+                    && !self.moves[address].contains(&other) {
+                    self.moves[address].push(other);
+                    self.peers[address].push(other);
+                }
+            }
+            self.moves[address].sort_unstable();
+            self.peers[address].sort_unstable();
+        }
+    }
+
+    /// add_neighbours - cells side by side on a grid of n states may not hold consecutive
+    /// states (non-consecutive), counting across the edges if wrap
+    pub fn add_neighbours(&mut self, n: usize, wrap: bool) {
+        for address in 0..n * n {
+            self.neighbours[address] = offsets(n, address, &[(0, 1), (1, 0)], wrap);
+        }
+    }
+}
+
+// offsets - cells a step (row and column offset, either sign) from address on a grid of
+// n states, in reading order - with wrap a step off one edge comes back on the other, as
+// on a torus
+fn offsets(n: usize, address: usize, steps: &[(usize, usize)], wrap: bool) -> Vec<usize> {
+    let (row, col) = ((address / n) as isize, (address % n) as isize);
+    let side = n as isize;
+    let mut cells: Vec<usize> = steps.iter()
+        .flat_map(|&(dr, dc)| {
+            let (dr, dc) = (dr as isize, dc as isize);
+            [(dr, dc), (dr, -dc), (-dr, dc), (-dr, -dc)]
+        })
+        .map(|(dr, dc)| if wrap { ((row + dr).rem_euclid(side), (col + dc).rem_euclid(side)) } else { (row + dr, col + dc) })
+        .filter(|&(r, c)| r >= 0 && c >= 0 && r < side && c < side)
+        .map(|(r, c)| r as usize * n + c as usize)
+        .filter(|&a| a != address)
+        .collect();
+    cells.sort_unstable();
+    cells.dedup();
+    cells
+}
+
+// lines - the rows then the columns of a grid of n states, as units
+fn lines(n: usize) -> Vec<(Unit, Vec<usize>)> {
+    let mut units = Vec::with_capacity(3 * n);
+    for row in 0..n {
+        units.push((Unit::Row(row), (0..n).map(|col| row * n + col).collect()));
+    }
+    for col in 0..n {
+        units.push((Unit::Col(col), (0..n).map(|row| row * n + col).collect()));
+    }
+    units
+}
+
+// blocks - the blocks bw wide and bh high of a grid of n states, as units
+// (none unless bw*bh is n)
+fn blocks(n: usize, bw: usize, bh: usize) -> Vec<(Unit, Vec<usize>)> {
+    let mut units = Vec::with_capacity(n);
+    if bw * bh == n && n > 0 {
+        let stacks = n / bw;
+        for b in 0..n {
+            let (by, bx) = ((b / stacks) * bh, (b % stacks) * bw);
+            units.push((Unit::Block(b), (0..n).map(|k| (by + k / bw) * n + bx + k % bw).collect()));
+        }
+    }
+    units
+}
+
+// jigsaw_regions - the regions of a grid of n states, given the region of each cell
+fn jigsaw_regions(n: usize, regions: &[usize]) -> Vec<(Unit, Vec<usize>)> {
+    (0..n).map(|r| (Unit::Region(r), (0..n * n).filter(|&a| regions[a] == r).collect())).collect()
+}
+
+impl Variant {
+    /// name - name of the variant, as used in the config line (after a +)
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Diagonals => "diagonals",
+            Variant::Hyper => "hyper",
+            Variant::AntiKnight => "antiknight",
+            Variant::AntiKing => "antiking",
+            Variant::NonConsecutive => "nonconsecutive",
+            Variant::Toroidal => "toroidal",
+        }
+    }
+
+    /// from_name - look up a variant by name, also accepting the usual puzzle names
+    pub fn from_name(name: &str) -> Option<Variant> {
+        match name {
+            "x" => Some(Variant::Diagonals),
+            "windoku" => Some(Variant::Hyper),
+            "anti-knight" => Some(Variant::AntiKnight),
+            "anti-king" => Some(Variant::AntiKing),
+            "non-consecutive" => Some(Variant::NonConsecutive),
+            "torus" | "wraparound" => Some(Variant::Toroidal),
+            _ => VARIANTS.iter().copied().find(|v| v.name() == name),
+        }
+    }
+
+    // units - extra units the variant adds to a grid of n states with boxes bw by bh
+    // hyper windows sit one cell in from each box, one between each pair of stacks and
+    // bands, so that a 9x9 grid has four (those which would run off the grid are left out)
+    fn units(&self, n: usize, bw: usize, bh: usize) -> Vec<(Unit, Vec<usize>)> {
+        match self {
+            Variant::Diagonals => vec![
+                (Unit::Diagonal(0), (0..n).map(|k| k * n + k).collect()),
+                (Unit::Diagonal(1), (0..n).map(|k| k * n + n - 1 - k).collect()),
+            ],
+            Variant::Hyper => {
+                let tops: Vec<usize> = (0..n / bh - 1).map(|k| 1 + k * (bh + 1)).filter(|t| t + bh <= n).collect();
+                let lefts: Vec<usize> = (0..n / bw - 1).map(|k| 1 + k * (bw + 1)).filter(|l| l + bw <= n).collect();
+                let mut units = Vec::new();
+                for &top in &tops {
+                    for &left in &lefts {
+                        let cells = (0..n).map(|k| (top + k / bw) * n + left + k % bw).collect();
+                        units.push((Unit::Window(units.len()), cells));
+                    }
+                }
+                units
+            }
+            Variant::AntiKnight | Variant::AntiKing | Variant::NonConsecutive | Variant::Toroidal => Vec::new(),
+        }
+    }
+
+    // moves - steps (row and column offset, either sign) to the cells the variant makes
+    // peers, rather than adding units
+    fn moves(&self) -> &'static [(usize, usize)] {
+        match self {
+            Variant::AntiKnight => &[(1, 2), (2, 1)],
+            Variant::AntiKing => &[(1, 1)], // the rest share a row or column already
+            _ => &[],
+        }
+    }
+}
+
+impl Rating {
+    /// grade - difficulty in words, from the hardest technique needed
+    /// (Diabolical when the techniques can't finish the grid)
+    pub fn grade(&self) -> &'static str {
+        if !self.solved {
+            return "Diabolical"; // needs search
+        }
+        match self.hardest {
+            None | Some(Technique::FullHouse) | Some(Technique::NakedSingle) | Some(Technique::HiddenSingle) => "Easy",
+            Some(Technique::InniesOuties) | Some(Technique::CageSum) | Some(Technique::CageArithmetic)
+            | Some(Technique::Thermometer)
+            | Some(Technique::ArrowSum) | Some(Technique::SandwichSum) | Some(Technique::Skyscraper)
+            | Some(Technique::XSum) | Some(Technique::LittleKiller) | Some(Technique::Dots)
+            | Some(Technique::GreaterThan) | Some(Technique::LockedCandidates) => "Medium",
+            Some(Technique::NakedPair) | Some(Technique::HiddenPair) | Some(Technique::NakedTriple) => "Hard",
+            Some(Technique::XWing) => "Fiendish",
+        }
+    }
+}
+
+impl Cell {
+    pub(crate) fn empty(states: u8) -> Cell {
+        Cell {
+            solved: false,                            // whether the cell is solved
+            given: false,
+            solution: 0,                              // don't care
+            possible: vec![false; states as usize],   // not computed
+            disallowed: vec![false; states as usize], // not computed
+            highlight: 0,
+        }
+    }
+}
+
+impl Grid {
+    /// new - create an empty grid, with boxes as square as the number of states allows
+    /// (see default_boxes - NotSquare status if there are none)
+    pub fn new(states: &str) -> Grid {
+        let nstates = states.chars().count();
+        match default_boxes(nstates) {
+            Some((w, h)) => Grid::empty(states, w, h, GridStatus::Empty),
+            None => Grid::empty(states, 0, 0, GridStatus::NotSquare),
+        }
+    }
+
+    /// with_boxes - create an empty grid with boxes w columns wide and h rows high
+    /// (BadBoxes unless w*h is the number of states)
+    pub fn with_boxes(states: &str, w: usize, h: usize) -> Result<Grid, GridError> {
+        let nstates = states.chars().count();
+        if w * h != nstates || nstates == 0 {
+            return Err(GridError::BadBoxes { states: nstates, width: w, height: h });
+        }
+        Ok(Grid::empty(states, w, h, GridStatus::Empty))
+    }
+
+    /// latin - create an empty Latin square: rows and columns but no boxes, so any number
+    /// of states will do (with greater-than signs, a Futoshiki)
+    pub fn latin(states: &str) -> Grid {
+        Grid::empty(states, 0, 0, GridStatus::Empty)
+    }
+
+    /// is_latin - whether the grid has neither boxes nor jigsaw regions, only rows and columns
+    pub fn is_latin(&self) -> bool {
+        self.box_w == 0 && self.regions.is_empty() && self.status != GridStatus::NotSquare
+    }
+
+    /// jigsaw - create an empty grid whose boxes are irregular regions, given the region
+    /// of each cell in reading order (any numbering) - each region must be n connected cells
+    pub fn jigsaw(states: &str, regions: &[usize]) -> Result<Grid, GridError> {
+        let mut g = Grid::empty(states, 0, 0, GridStatus::Empty);
+        if regions.len() != g.size {
+            return Err(GridError::WrongLength { expected: g.size, got: regions.len() });
+        }
+        g.set_regions(regions)?;
+        Ok(g)
+    }
+
+    // set_regions - check and use jigsaw regions, renumbering them in reading order
+    fn set_regions(&mut self, regions: &[usize]) -> Result<(), GridError> {
+        let n = self.states as usize;
+        let wrap = self.variants.contains(&Variant::Toroidal);
+        let mut number = Vec::new();
+        let renumbered: Vec<usize> = regions.iter().map(|r| {
+            number.iter().position(|x| x == r).unwrap_or_else(|| {
+                number.push(*r);
+                number.len() - 1
+            })
+        }).collect();
+
+        for region in 0..number.len() {
+            let cells: Vec<usize> = (0..self.size).filter(|&a| renumbered[a] == region).collect();
+            if cells.len() != n {
+                return Err(GridError::RegionSize { region, cells: cells.len(), expected: n });
+            }
+            // flood fill from the first cell, orthogonally (and across the edges on a torus)
+            let mut reached = vec![cells[0]];
+            let mut k = 0;
+            while k < reached.len() {
+                for a in offsets(n, reached[k], &[(0, 1), (1, 0)], wrap) {
+                    if renumbered[a] == region && !reached.contains(&a) {
+                        reached.push(a);
+                    }
+                }
+                k += 1;
+            }
+            if reached.len() != n {
+                return Err(GridError::RegionSplit { region });
+            }
+        }
+
+        self.regions = renumbered;
+        self.rebuild_units();
+        Ok(())
+    }
+
+    /// add_variant - add the rule of a variant (once) - hyper windows need boxes
+    pub fn add_variant(&mut self, variant: Variant) -> Result<(), GridError> {
+        if variant == Variant::Hyper && self.box_w == 0 {
+            return Err(GridError::NotForLayout("hyper"));
+        }
+        if !self.variants.contains(&variant) {
+            self.variants.push(variant);
+            self.rebuild_units();
+        }
+        Ok(())
+    }
+
+    /// add_constraint - add a rule on particular cells (cages may not overlap)
+    pub fn add_constraint(&mut self, constraint: Constraint) -> Result<(), GridError> {
+        self.push_constraint(constraint)?;
+        constraint::refresh_negatives(&mut self.constraints, self.states as usize);
+        Ok(())
+    }
+
+    // push_constraint - add_constraint, leaving the negative constraints to be refreshed
+    fn push_constraint(&mut self, constraint: Constraint) -> Result<(), GridError> {
+        let n = self.states as usize;
+        if let Some(&a) = constraint.cells().iter().find(|&&a| a >= self.size) {
+            return Err(GridError::WrongLength { expected: self.size, got: a + 1 });
+        }
+        if constraint.is_cage() {
+            let mut caged = self.constraints.iter().filter(|c| c.is_cage()).flat_map(|c| c.cells());
+            if let Some(&a) = caged.find(|a| constraint.cells().contains(a)) {
+                return Err(GridError::Overlap { row: a / n, col: a % n });
+            }
+        }
+        self.constraints.push(constraint);
+        Ok(())
+    }
+
+    // rebuild_units - work out the unit table again after the layout or variants change
+    fn rebuild_units(&mut self) {
+        let n = self.states as usize;
+        let (bw, bh) = (self.box_w as usize, self.box_h as usize);
+        let mut units = lines(n);
+        if self.regions.is_empty() {
+            units.extend(blocks(n, bw, bh));
+        } else {
+            units.extend(jigsaw_regions(n, &self.regions));
+        }
+        for variant in &self.variants {
+            units.extend(variant.units(n, bw, bh));
+        }
+        let mut units = Units::from_units(self.size, units);
+        let wrap = self.variants.contains(&Variant::Toroidal);
+        for variant in &self.variants {
+            units.add_moves(n, variant.moves(), wrap);
+        }
+        if self.variants.contains(&Variant::NonConsecutive) {
+            units.add_neighbours(n, wrap);
+        }
+        self.units = Arc::new(units);
+    }
+
+    // empty - grid of unsolved cells with the given box shape and status
+    fn empty(states: &str, w: usize, h: usize, s: GridStatus) -> Grid {
+        let nstates = states.chars().count() as u8;
+        let int_sq_root = (nstates as f64).sqrt() as u32;
+
+        Grid {
+            name: format!("Empty grid for {}", states),
+            state_dict: states.to_owned(),
+            status: s,
+            states: nstates,
+            isqrt: int_sq_root as u8,
+            box_w: w as u8,
+            box_h: h as u8,
+            size: nstates as usize * nstates as usize,
+            cells: vec![Cell::empty(nstates); nstates as usize * nstates as usize],
+            symbols: states.chars().collect(),
+            regions: Vec::new(),
+            variants: Vec::new(),
+            constraints: Vec::new(),
+            units: Arc::new(Units::standard(nstates as usize, w, h)),
+        }
+    }
+}
+
+// Implement grid methods
+impl Grid {
+    /// isempty - is an initialised empty grid
+    pub fn isempty(&self) -> bool {
+        self.status == GridStatus::Empty
+    }
+
+    pub fn bodge(&mut self, title: String, arr: Vec<u8>) -> Result<u32, GridError> {
+        self.name = title;
+
+        if self.size != arr.len() {
+            return Err(GridError::WrongLength { expected: self.size, got: arr.len() });
+        }
+
+        // populate grid with empty cells, unless solved (in 1...9)
+        let n = self.states as usize;
+        let mut used: u32 = 0;
+        for (i, &value) in arr.iter().enumerate() {
+            if value > self.states {
+                let symbol = char::from_digit(value as u32, 36).unwrap_or('?');
+                return Err(GridError::BadSymbol { symbol, row: i / n, col: i % n });
+            }
+            if value > 0 {
+                self.give(i, value - 1);
+                used += 1;
+            }
+        }
+
+        Ok(used)
+    }
+
+    /// load - get grid from file (see from_sud for the format)
+    /// (the grid is left alone if that fails)
+    pub fn load(&mut self, filename: String) -> Result<(), GridError> {
+        let text = std::fs::read_to_string(&filename)
+            .map_err(|e| GridError::Io { filename: filename.clone(), message: e.to_string() })?;
+        *self = Grid::from_sud(&text, &filename)?;
+        Ok(())
+    }
+
+    /// from_sud - grid from text in .sud format
+    /// line 1 is the title, then an optional config line `<boxes> <blank> <states> [notes]`
+    /// (default "3 - 123456789"), where boxes is the side of square boxes, `<w>x<h>` for
+    /// boxes w columns wide and h rows high, `jigsaw` for irregular regions or `latin` for
+    /// none (a Latin square, of any number of states), and notes
+    /// may include variants as `+<name>` (e.g. +diagonals), then the cells - any character
+    /// which is neither a state nor the blank is ignored, so borders and spacing can be
+    /// used freely - and for a jigsaw the region letters
+    /// constraints such as killer cages are lines of their own anywhere after the config
+    /// line, e.g. `cage 15 r1c1 r1c2 r2c1` (see Constraint::parse)
+    /// a text holding just a one-line puzzle (see from_line) is also accepted, named by name
+    pub fn from_sud(text: &str, name: &str) -> Result<Grid, GridError> {
+        let mut lines: Vec<&str> = text.lines().collect();
+        if lines.is_empty() {
+            return Err(GridError::Empty);
+        }
+        let title = lines.remove(0).trim().to_owned();
+
+        // a file holding just a one-line puzzle has no title (with a config line or
+        // cells after it, the first line is a title, even one that reads as a puzzle)
+        if lines.iter().all(|l| l.trim().is_empty())
+            && let Ok(g) = Grid::from_line(&title, name)
+        {
+            return Ok(g);
+        }
+
+        // look for the config line (first non-blank line after the title)
+        let mut blank = '-';
+        let mut states = "123456789".to_owned();
+        let mut layout = Layout::Boxes(3, 3);
+        let mut variants = Vec::new();
+        // (a line of cells never has a single character followed by several, so one that
+        // does is a config line, and a token there which can't be read is an error)
+        if let Some(pos) = lines.iter().position(|l| !l.trim().is_empty()) {
+            let tokens: Vec<&str> = lines[pos].split_whitespace().collect();
+            let config = if tokens.len() >= 3 && tokens[1].chars().count() == 1 {
+                let count = tokens[2].chars().count();
+                match parse_boxes(tokens[0]) {
+                    Some(Layout::Boxes(w, h)) if count == w * h => Some(Layout::Boxes(w, h)),
+                    Some(Layout::Boxes(w, h)) if count > 1 => {
+                        return Err(GridError::BadBoxes { states: count, width: w, height: h });
+                    }
+                    None if count > 1 && (tokens[0] == "cube" || poly::Polydoku::shape_named(tokens[0]).is_some()) => {
+                        return Err(GridError::PolydokuShape(tokens[0].to_owned()));
+                    }
+                    None if count > 1 => return Err(GridError::BadConfig(tokens[0].to_owned())),
+                    shape => shape.filter(|s| matches!(s, Layout::Jigsaw | Layout::Latin)),
+                }
+            } else {
+                None
+            };
+            if let Some(shape) = config {
+                blank = tokens[1].chars().next().unwrap();
+                states = tokens[2].to_owned();
+                layout = shape;
+                variants = tokens[3..].iter().filter_map(|t| t.strip_prefix('+')).collect();
+                lines.drain(..=pos);
+            }
+        }
+
+        let mut g = match layout {
+            Layout::Boxes(w, h) => Grid::with_boxes(&states, w, h)?,
+            Layout::Jigsaw | Layout::Latin => Grid::empty(&states, 0, 0, GridStatus::Empty),
+        };
+        g.name = title;
+        for name in variants {
+            let variant = Variant::from_name(name).ok_or_else(|| GridError::UnknownVariant(name.to_owned()))?;
+            g.add_variant(variant)?;
+        }
+
+        // take out the constraint lines
+        let mut grid_lines = Vec::new();
+        for line in lines {
+            match Constraint::parse(line, g.states as usize) {
+                Some(constraint) => g.push_constraint(constraint?)?,
+                None => grid_lines.push(line),
+            }
+        }
+        constraint::refresh_negatives(&mut g.constraints, g.states as usize);
+        let lines = grid_lines;
+
+        // populate cells in reading order, noting where they end
+        let mut address = 0;
+        let mut rest = lines.len();
+        for (k, line) in lines.iter().enumerate() {
+            for ch in line.chars() {
+                if address == g.size {
+                    break;
+                }
+                if ch == blank {
+                    address += 1;
+                } else if let Some(sol) = g.symbols.iter().position(|&s| s == ch) {
+                    g.give(address, sol as Snumb);
+                    address += 1;
+                }
+            }
+            if address == g.size {
+                rest = k + 1;
+                break;
+            }
+        }
+        if address != g.size {
+            return Err(GridError::WrongLength { expected: g.size, got: address });
+        }
+
+        // a jigsaw has a second block after the cells, a letter or digit for the region of
+        // each cell (anything else is ignored, so boundaries can be drawn)
+        if layout == Layout::Jigsaw {
+            let letters: Vec<usize> = lines[rest..].iter()
+                .flat_map(|l| l.chars())
+                .filter(|ch| ch.is_alphanumeric())
+                .map(|ch| ch as usize)
+                .collect();
+            if letters.len() != g.size {
+                return Err(GridError::WrongLength { expected: g.size, got: letters.len() });
+            }
+            g.set_regions(&letters)?;
+        }
+
+        Ok(g)
+    }
+
+    /// save - save current grid to file
+    /// a name ending .sud gets the readable layout of the test files, anything else the
+    /// one-line format - either loads back with load()
+    pub fn save(&self, filename: String) -> Result<(), GridError> {
+        std::fs::write(&filename, self.file_text(&filename))
+            .map_err(|e| GridError::Io { filename: filename.clone(), message: e.to_string() })
+    }
+
+    /// file_text - text to save under filename, .sud layout or one line
+    pub fn file_text(&self, filename: &str) -> String {
+        if filename.ends_with(".sud") { self.to_sud() } else { self.to_line() + "\n" }
+    }
+
+    /// to_sud - grid as text in .sud format
+    pub fn to_sud(&self) -> String {
+        let n = self.states as usize;
+        let (bw, bh) = self.box_lines();
+        let blank = if self.symbols.contains(&'-') { '.' } else { '-' };
+        let used = self.cells.iter().filter(|c| c.solved).count();
+        let boxes = if !self.regions.is_empty() {
+            "jigsaw".to_owned()
+        } else if self.box_w == 0 {
+            "latin".to_owned()
+        } else if bw == bh {
+            bw.to_string()
+        } else {
+            format!("{}x{}", bw, bh)
+        };
+
+        let variants: String = self.variants.iter().map(|v| format!("+{} ", v.name())).collect();
+        let mut text = format!("{}\n{} {} {} {}{}/{}\n", self.name, boxes, blank, self.state_dict, variants,
+                               used, self.size);
+        for row in 0..n {
+            if row % bh == 0 {
+                text.push('\n');
+            }
+            let mut line = String::new();
+            for col in 0..n {
+                if col != 0 {
+                    line.push_str(if col % bw == 0 { "   " } else { " " });
+                }
+                let cell = &self.cells[row * n + col];
+                line.push(if cell.solved { self.symbols[cell.solution as usize] } else { blank });
+            }
+            text.push_str(&line);
+            text.push('\n');
+        }
+
+        // then the region of each cell as a letter
+        if !self.regions.is_empty() {
+            let letters: Vec<char> = REGION_LETTERS.chars().collect();
+            text.push('\n');
+            for row in self.regions.chunks(n) {
+                let line: Vec<String> = row.iter().map(|&r| letters[r].to_string()).collect();
+                text.push_str(&line.join(" "));
+                text.push('\n');
+            }
+        }
+
+        // then a line for each constraint
+        if !self.constraints.is_empty() {
+            text.push('\n');
+            for constraint in &self.constraints {
+                text.push_str(&constraint.to_text(n));
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    /// to_line - grid as a single line of cells in reading order, '.' for unsolved
+    pub fn to_line(&self) -> String {
+        self.cells.iter()
+            .map(|cell| if cell.solved { self.symbols[cell.solution as usize] } else { '.' })
+            .collect()
+    }
+
+    /// from_line - grid from a single line of cells in reading order, with '.', '-' or '0'
+    /// (unless a state) for unsolved cells and the default states for that size
+    pub fn from_line(line: &str, name: &str) -> Result<Grid, GridError> {
+        let line = line.trim();
+        let cells = line.chars().count();
+        let n = ((cells as f64).sqrt() as usize).clamp(4, 64);
+        if n * n != cells {
+            return Err(GridError::WrongLength { expected: n * n, got: cells });
+        }
+
+        let mut g = Grid::new(default_states(n));
+        if !g.isempty() {
+            return Err(GridError::NotSquare { states: n });
+        }
+        g.name = name.to_owned();
+        for (address, ch) in line.chars().enumerate() {
+            if let Some(sol) = g.symbols.iter().position(|&s| s == ch) {
+                g.give(address, sol as Snumb);
+            } else if !matches!(ch, '.' | '-' | '0') {
+                return Err(GridError::BadSymbol { symbol: ch, row: address / n, col: address % n });
+            }
+        }
+        Ok(g)
+    }
+
+    /// validate - check logic of current grid, returning every state repeated within a
+    /// unit (none if valid) - all the cells involved are highlighted, as are those of any
+    /// constraint broken (see violations)
+    pub fn validate(&mut self) -> Vec<Conflict> {
+        let conflicts = self.conflicts();
+        let mut blamed: Vec<usize> = conflicts.iter().flat_map(|c| c.cells.iter().copied()).collect();
+        blamed.extend(self.violations().into_iter().flat_map(|v| v.cells));
+        for address in blamed {
+            self.cells[address].highlight = 2;
+        }
+        conflicts
+    }
+
+    /// violations - every constraint the solved cells already break
+    pub fn violations(&self) -> Vec<Violation> {
+        let values = self.values();
+        self.constraints.iter().enumerate()
+            .filter_map(|(k, c)| c.broken(&values, self.states as usize).map(|(cells, reason)| Violation { constraint: k, cells, reason }))
+            .collect()
+    }
+
+    /// violation_text - describe a violation for the user
+    pub fn violation_text(&self, violation: &Violation) -> String {
+        let cells: Vec<String> = violation.cells.iter().map(|&a| self.cell_name(a)).collect();
+        format!("{} {}: {}", self.constraint_name(violation.constraint), violation.reason, cells.join(", "))
+    }
+
+    // values - value of each cell as for constraints, 0 if unsolved otherwise state+1
+    fn values(&self) -> Vec<u8> {
+        self.cells.iter().map(|c| if c.solved { c.solution + 1 } else { 0 }).collect()
+    }
+
+    /// conflicts - every state repeated within a unit, rows then columns then blocks, then
+    /// a chess move apart, then consecutive states side by side
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let n = self.states as usize;
+        let mut conflicts = Vec::new();
+        for (h, house) in self.houses().iter().enumerate() {
+            let mut holding = vec![Vec::new(); n];
+            for &address in house {
+                if self.cells[address].solved {
+                    holding[self.cells[address].solution as usize].push(address);
+                }
+            }
+            for (state, cells) in holding.into_iter().enumerate() {
+                if cells.len() > 1 {
+                    conflicts.push(Conflict { unit: self.units.kinds[h], state: state as Snumb, cells });
+                }
+            }
+        }
+        let solved = |a: usize| self.cells[a].solved.then_some(self.cells[a].solution);
+        for address in 0..self.size {
+            let Some(state) = solved(address) else { continue };
+            for &other in self.units.moves[address].iter().filter(|&&a| a > address) {
+                if solved(other) == Some(state) {
+                    conflicts.push(Conflict { unit: move_unit(address, other, n), state, cells: vec![address, other] });
+                }
+            }
+        }
+        for address in 0..self.size {
+            let Some(state) = solved(address) else { continue };
+            for &other in self.units.neighbours[address].iter().filter(|&&a| a > address) {
+                if let Some(s) = solved(other).filter(|&s| s.abs_diff(state) == 1) {
+                    conflicts.push(Conflict { unit: Unit::Neighbours(address), state: s.min(state), cells: vec![address, other] });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// conflict_text - describe a conflict for the user
+    pub fn conflict_text(&self, conflict: &Conflict) -> String {
+        let cells: Vec<String> = conflict.cells.iter().map(|&a| self.cell_name(a)).collect();
+        let symbol = self.symbols[conflict.state as usize];
+        match conflict.unit {
+            Unit::Knight(_) | Unit::King(_) => format!("'{}' repeated a {} apart: {}", symbol, conflict.unit.kind(), cells.join(", ")),
+            Unit::Neighbours(_) => format!("'{}' and '{}' side by side: {}", symbol,
+                                           self.symbols[conflict.state as usize + 1], cells.join(", ")),
+            _ => format!("'{}' repeated in {}: {}", symbol, self.unit_name(conflict.unit), cells.join(", ")),
+        }
+    }
+
+    // give(a,state) - set a cell as given by the puzzle
+    fn give(&mut self, address: usize, sol: Snumb) {
+        self.cells[address].solved = true;
+        self.cells[address].given = true;
+        self.cells[address].solution = sol;
+    }
+
+    /// claim(r,c,state) - set a blank to a solution at (row,col)
+    pub fn claim_rc(&mut self, row: usize, col: usize, sol: Snumb) -> Result<(), GridError> {
+        let address = row * self.states as usize + col;
+        self.claim_a(address, sol)
+    }
+
+    /// claim_a(a,state) - set a blank to a solution at addr=a
+    pub fn claim_a(&mut self, address: usize, sol: Snumb) -> Result<(), GridError> {
+        if self.cells[address].solved {
+            let n = self.states as usize;
+            return Err(GridError::AlreadySolved { row: address / n, col: address % n });
+        }
+        self.cells[address].solved = true;
+        self.cells[address].solution = sol;
+        self.cells[address].highlight = 1;
+        Ok(())
+    }
+
+    /// solve_next - claim one cell, the gap in any unit with all but one state claimed
+    /// (returns the number of cells claimed, 0 when stuck)
+    pub fn solve_next(&mut self) -> Result<u8, GridError> {
+        // variables which simplifies expressions/readability
+        let n = self.states as usize;    // n = number of states (9 for Sudoku)
+        let units = Arc::clone(&self.units);
+
+        // a) do one-off walk over the units to set 'state claimed' boolmaps
+        let mut ticked = vec![vec![false; n]; units.cells.len()];
+        for (u, cells) in units.cells.iter().enumerate() {
+            for &address in cells {
+                if self.cells[address].solved {
+                    let sol = self.cells[address].solution as usize;
+                    if ticked[u][sol] {
+                        return Err(GridError::Contradiction { unit: units.kinds[u], state: self.symbols[sol] });
+                    }
+                    ticked[u][sol] = true;
+                }
+            }
+        }
+
+        // b) check boolmaps for '8/9' used ... by row, then column, then block
+        for (u, cells) in units.cells.iter().enumerate() {
+            // - see if can make immediate claim
+            if ticked[u].iter().filter(|&&t| t).count() == n - 1 {
+                // which state is missing, and where is the gap?
+                let missed = ticked[u].iter().position(|&t| !t).unwrap_or(0) as Snumb;
+                if let Some(&address) = cells.iter().find(|&&a| !self.cells[a].solved) {
+                    self.claim_a(address, missed)?;
+                    return Ok(1);
+                }
+            }
+        }
+
+        // return value is number of cells added
+        // (this is used to re-call the fn until exhaustion)
+        Ok(0)
+    }
+
+    /// count_solutions - brute-force count of the solutions, stopping once limit is reached
+    /// (a limit of 2 is enough to tell unique from multiple solutions)
+    pub fn count_solutions(&self, limit: usize) -> usize {
+        match Search::from_grid(self) {
+            Ok(mut s) => {
+                s.limit = limit;
+                s.run();
+                s.solutions
+            }
+            Err(_) => 0, // givens already conflict
+        }
+    }
+
+    /// solve - brute-force fill in the first solution found (new cells highlighted)
+    /// returns number of solutions, up to 2 - anything other than 1 leaves the grid alone
+    /// (a Contradiction if the solved cells already conflict)
+    pub fn solve(&mut self) -> Result<usize, GridError> {
+        let mut s = Search::from_grid(self)?;
+        s.limit = 2;
+        s.run();
+        if s.solutions == 1 {
+            for address in 0..self.size {
+                if !self.cells[address].solved {
+                    self.claim_a(address, s.first[address] - 1)?;
+                }
+            }
+        }
+        Ok(s.solutions)
+    }
+
+    /// minimise - remove givens one at a time while the solution remains unique
+    /// returns the addresses of the redundant givens, which are left unsolved
+    /// (one pass is enough: a given which can't be removed never becomes removable
+    /// once other givens have gone)
+    pub fn minimise(&mut self) -> Result<Vec<usize>, GridError> {
+        let order: Vec<usize> = (0..self.size).collect();
+        self.minimise_in(&order)
+    }
+
+    /// minimise_in - minimise, trying the givens in the order of addresses given
+    pub fn minimise_in(&mut self, order: &[usize]) -> Result<Vec<usize>, GridError> {
+        let solutions = self.count_solutions(2);
+        if solutions != 1 {
+            return Err(GridError::NotUnique { solutions });
+        }
+
+        let mut redundant = Vec::new();
+        for &address in order {
+            if !self.cells[address].solved {
+                continue;
+            }
+            self.cells[address].solved = false;
+            self.cells[address].given = false;
+            if self.count_solutions(2) == 1 {
+                redundant.push(address);
+            } else {
+                self.cells[address].solved = true;
+                self.cells[address].given = true;
+            }
+        }
+
+        Ok(redundant)
+    }
+
+    /// generate - new minimal puzzle with n states and default boxes, using the random
+    /// number generator
+    pub fn generate(n: usize, rng: &mut Rng) -> Result<Grid, GridError> {
+        Grid::generate_in(Grid::sized(n)?, rng)
+    }
+
+    /// sized - empty grid of n states, with the default states and boxes for that size
+    pub fn sized(n: usize) -> Result<Grid, GridError> {
+        if n > 64 {
+            return Err(GridError::TooLarge { states: n, max: 64 });
+        }
+        let g = Grid::new(default_states(n));
+        if !g.isempty() {
+            return Err(GridError::NotSquare { states: n });
+        }
+        Ok(g)
+    }
+
+    /// generate_in - new minimal puzzle on the empty grid g, keeping its states and boxes
+    /// a random full grid is found by search, then givens removed in random order
+    /// (NotUnique with no solutions if its variants leave no full grid)
+    pub fn generate_in(mut g: Grid, rng: &mut Rng) -> Result<Grid, GridError> {
+        let n = g.states as usize;
+        g.name = format!("Generated {}x{}", n, n);
+
+        let mut s = Search::from_grid(&g)?;
+        s.random = Some(rng.clone());
+        s.run();
+        *rng = s.random.take().unwrap();
+        if s.solutions == 0 {
+            return Err(GridError::NotUnique { solutions: 0 }); // variants no grid can meet
+        }
+        for address in 0..g.size {
+            g.give(address, s.first[address] - 1);
+        }
+
+        let mut order: Vec<usize> = (0..g.size).collect();
+        rng.shuffle(&mut order);
+        g.minimise_in(&order)?;
+        Ok(g)
+    }
+
+    /// update_status - set status from the cells (NotSquare is left alone)
+    pub fn update_status(&mut self) {
+        if self.status == GridStatus::NotSquare {
+            return;
+        }
+        self.status = if Search::from_grid(self).is_err() {
+            GridStatus::Invalid
+        } else if self.cells.iter().all(|c| c.solved) {
+            GridStatus::Solved
+        } else {
+            GridStatus::Incomplete
+        };
+    }
+
+    // remap - rearrange the cells, new (row,col) taking the cell found at source(row,col)
+    // (jigsaw regions are checked again first, so one splitting them is an error and
+    // leaves the grid alone)
+    fn remap(&mut self, source: impl Fn(usize, usize) -> (usize, usize)) -> Result<(), GridError> {
+        let n = self.states as usize;
+
+        // jigsaw regions move with the cells
+        if !self.regions.is_empty() {
+            let regions: Vec<usize> = (0..self.size).map(|a| {
+                let (r, c) = source(a / n, a % n);
+                self.regions[r * n + c]
+            }).collect();
+            self.set_regions(&regions)?;
+        }
+
+        let mut cells = Vec::with_capacity(self.size);
+        for row in 0..n {
+            for col in 0..n {
+                let (r, c) = source(row, col);
+                cells.push(self.cells[r * n + c].clone());
+            }
+        }
+        self.cells = cells;
+
+        // and so do constraints
+        let mut to = vec![0; self.size];
+        for a in 0..self.size {
+            let (r, c) = source(a / n, a % n);
+            to[r * n + c] = a;
+        }
+        for constraint in self.constraints.iter_mut() {
+            constraint.map_cells(|a| to[a]);
+        }
+        Ok(())
+    }
+
+    /// relabel - swap symbols around, so that state s becomes `map[s]`
+    /// (not for grids with constraints or non-consecutive, as they depend on the values)
+    pub fn relabel(&mut self, map: &[usize]) -> Result<(), GridError> {
+        if !is_permutation(map, self.states as usize) {
+            return Err(GridError::BadPermutation("relabel map"));
+        }
+        if !self.constraints.is_empty() || self.variants.contains(&Variant::NonConsecutive) {
+            return Err(GridError::NotForLayout("relabel"));
+        }
+        for cell in self.cells.iter_mut() {
+            if cell.solved {
+                cell.solution = map[cell.solution as usize] as Snumb;
+            }
+        }
+        Ok(())
+    }
+
+    /// permute_rows - reorder the rows within a band, row k of the band taking row `order[k]`
+    /// (variant units such as diagonals stay put, so may no longer hold)
+    pub fn permute_rows(&mut self, band: usize, order: &[usize]) -> Result<(), GridError> {
+        let bh = self.box_h as usize;
+        if bh == 0 || band >= self.states as usize / bh || !is_permutation(order, bh) {
+            return Err(GridError::BadPermutation("row order within a band"));
+        }
+        self.remap(|r, c| {
+            if r / bh == band { (band * bh + order[r % bh], c) } else { (r, c) }
+        })
+    }
+
+    /// permute_bands - reorder the bands (rows of blocks), band k taking band `order[k]`
+    pub fn permute_bands(&mut self, order: &[usize]) -> Result<(), GridError> {
+        let bh = self.box_h as usize;
+        if bh == 0 || !is_permutation(order, self.states as usize / bh) {
+            return Err(GridError::BadPermutation("band order"));
+        }
+        self.remap(|r, c| (order[r / bh] * bh + r % bh, c))
+    }
+
+    /// transpose - swap rows and columns (reflect in leading diagonal)
+    /// rectangular boxes turn on their side
+    pub fn transpose(&mut self) -> Result<(), GridError> {
+        self.remap(|r, c| (c, r))?;
+        self.turn_boxes();
+        Ok(())
+    }
+
+    /// rotate - turn the grid 90 degrees clockwise
+    pub fn rotate(&mut self) -> Result<(), GridError> {
+        let last = self.states as usize - 1;
+        self.remap(|r, c| (last - c, r))?;
+        self.turn_boxes();
+        Ok(())
+    }
+
+    // turn_boxes - swap box width and height, after rows and columns have been swapped
+    fn turn_boxes(&mut self) {
+        if self.box_w != self.box_h {
+            (self.box_w, self.box_h) = (self.box_h, self.box_w);
+            self.rebuild_units();
+        }
+    }
+
+    /// reflect - mirror the grid left to right
+    pub fn reflect(&mut self) -> Result<(), GridError> {
+        let last = self.states as usize - 1;
+        self.remap(|r, c| (r, last - c))
+    }
+
+    /// canonical - lexicographically minimal equivalent grid
+    /// cells compare in reading order (blank lowest) with states relabelled in order of
+    /// first appearance, so grids which differ only by symbols have the same canonical form
+    /// only for grids of up to 9 states with plain boxes: anything else (jigsaw, Latin,
+    /// variants or constraints) is NotForLayout and larger grids TooLarge, rather than a
+    /// weaker form - rectangular boxes aren't transposed, as that changes their shape
+    pub fn canonical(&self) -> Result<Grid, GridError> {
+        let labels = self.canonical_labels()?;
+        let mut g = Grid::with_boxes(&self.state_dict, self.box_w as usize, self.box_h as usize)?;
+        g.name = format!("{} (canonical)", self.name);
+        for (address, &label) in labels.iter().enumerate() {
+            if label > 0 {
+                g.give(address, label - 1);
+            }
+        }
+        Ok(g)
+    }
+
+    /// canonical_hash - stable hash (64-bit FNV-1a) of the canonical form
+    pub fn canonical_hash(&self) -> Result<u64, GridError> {
+        let labels = self.canonical_labels()?;
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for label in labels {
+            hash ^= label as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        Ok(hash)
+    }
+
+    // canonical_labels - canonical form as 0 for blank, otherwise relabelled state+1
+    // every transpose (square boxes only), stack and column order is tried, with the rows
+    // then chosen by a pruned search - this grows as (isqrt!)^(2*isqrt+2), so is limited to 9x9
+    fn canonical_labels(&self) -> Result<Vec<u8>, GridError> {
+        if self.status == GridStatus::NotSquare {
+            return Err(GridError::NotSquare { states: self.states as usize });
+        }
+        if self.box_w == 0 || !self.variants.is_empty() || !self.constraints.is_empty() {
+            return Err(GridError::NotForLayout("canonical form"));
+        }
+        if self.states > 9 {
+            return Err(GridError::TooLarge { states: self.states as usize, max: 9 });
+        }
+
+        let n = self.states as usize;
+        let (bw, bh) = (self.box_w as usize, self.box_h as usize);
+        let plain: Vec<u8> = self.cells.iter()
+            .map(|cell| if cell.solved { cell.solution + 1 } else { 0 })
+            .collect();
+        let flipped: Vec<u8> = (0..self.size).map(|a| plain[(a % n) * n + a / n]).collect();
+        let layouts = if bw == bh { vec![&plain, &flipped] } else { vec![&plain] };
+
+        let stack_perms = permutations(n / bw);
+        let perms = permutations(bw);
+        let mut best = vec![u8::MAX; self.size];
+        let mut colmap = vec![0; n];
+        for values in layouts {
+            for stacks in &stack_perms {
+                // every combination of column orders within the stacks
+                for combo in 0..perms.len().pow(stacks.len() as u32) {
+                    let mut rest = combo;
+                    for (s, &stack) in stacks.iter().enumerate() {
+                        let within = &perms[rest % perms.len()];
+                        rest /= perms.len();
+                        for k in 0..bw {
+                            colmap[s * bw + k] = stack * bw + within[k];
+                        }
+                    }
+                    let mut used = vec![false; n];
+                    let labels = vec![0u8; n + 1];
+                    canonical_rows(values, n, bh, &colmap, &mut used, &labels, 0, 0, 0, &mut best);
+                }
+            }
+        }
+        Ok(best)
+    }
+    // box_lines - columns and rows between the box separators drawn
+    // (none drawn if there are no boxes)
+    fn box_lines(&self) -> (usize, usize) {
+        if self.box_w == 0 {
+            (self.states as usize, self.states as usize)
+        } else {
+            (self.box_w as usize, self.box_h as usize)
+        }
+    }
+
+    // outline - group of each cell whose boundaries are drawn: killer cages (numbered from
+    // 1, 0 for uncaged cells) if there are any, otherwise jigsaw regions (empty if neither)
+    fn outline(&self) -> Vec<usize> {
+        if !self.constraints.iter().any(|c| c.is_cage()) {
+            return self.regions.clone();
+        }
+        let mut groups = vec![0; self.size];
+        for (k, cage) in self.constraints.iter().filter(|c| c.is_cage()).enumerate() {
+            for &a in cage.cells() {
+                groups[a] = k + 1;
+            }
+        }
+        groups
+    }
+
+    // sign - greater-than sign between cells a and b (before and after), drawn as more
+    // when a is the greater, less when b is, otherwise a space
+    fn sign(&self, a: usize, b: usize, more: char, less: char) -> char {
+        let greater = self.constraints.iter().find_map(|c| match c {
+            Constraint::Pair { rule: PairRule::Greater, cells } if cells.contains(&a) && cells.contains(&b) => Some(cells[0]),
+            _ => None,
+        });
+        match greater {
+            Some(g) if g == a => more,
+            Some(_) => less,
+            None => ' ',
+        }
+    }
+
+    // region_boundary - line drawn above row, with --- under cells whose group differs
+    // from the cell above and a corner (or |) where boundaries meet - everything is
+    // outside row 0, and a cage's sum is written on the boundary above its first cell
+    fn region_boundary(&self, r: &[usize], row: usize) -> String {
+        let n = self.states as usize;
+        let above = |col: usize| if row == 0 { usize::MAX } else { r[(row - 1) * n + col] };
+        let mut line = String::new();
+        for col in 0..n {
+            if col != 0 {
+                let (tl, tr, bl, br) = (above(col - 1), above(col), r[row * n + col - 1], r[row * n + col]);
+                let upright = tl != tr || bl != br;
+                let across = tl != bl || tr != br;
+                line.push(match (upright, across) {
+                    (true, true) => '+',
+                    (true, false) => '|',
+                    (false, true) => '-',
+                    (false, false) => ' ',
+                });
+            }
+            line.push_str(if above(col) != r[row * n + col] { "---" } else { "   " });
+        }
+
+        // cage labels over the boundary above each cage's first cell, running on over the
+        // next corner when longer (such as 120x)
+        let mut line: Vec<char> = line.chars().collect();
+        for col in 0..n {
+            let label = self.constraints.iter()
+                .find(|c| c.is_cage() && c.cells()[0] == row * n + col)
+                .and_then(|c| c.label());
+            for (k, ch) in label.unwrap_or_default().chars().enumerate() {
+                if col * 4 + k >= line.len() {
+                    line.resize(col * 4 + k + 1, ' ');
+                }
+                line[col * 4 + k] = ch;
+            }
+        }
+        line.into_iter().collect::<String>().trim_end().to_owned()
+    }
+
+    // ring_clues - the outside clues with where each sits on the ring around the grid
+    // (row or column 0 or n+1), those sharing a place joined by commas
+    fn ring_clues(&self) -> Vec<((usize, usize), String)> {
+        let n = self.states as usize;
+        let mut ring: Vec<((usize, usize), String)> = Vec::new();
+        for (at, clue) in self.constraints.iter().filter_map(|c| Some((c.ring(n)?, c.clue(n)?))) {
+            match ring.iter_mut().find(|(place, _)| *place == at) {
+                Some((_, text)) => *text = format!("{},{}", text, clue),
+                None => ring.push((at, clue)),
+            }
+        }
+        ring
+    }
+
+    // ring_edge - line of the outside clues above (row 0) or below (row n+1) the grid,
+    // each over its column's cells as they start at offsets after a margin of width
+    fn ring_edge(ring: &[((usize, usize), String)], row: usize, offsets: &[usize], width: usize) -> String {
+        let n = offsets.len();
+        let mut line: Vec<char> = Vec::new();
+        let mut put = |at: usize, text: &str| {
+            for (k, ch) in text.chars().enumerate() {
+                if at + k >= line.len() {
+                    line.resize(at + k + 1, ' ');
+                }
+                line[at + k] = ch;
+            }
+        };
+        for ((_, col), text) in ring.iter().filter(|((r, _), _)| *r == row) {
+            let len = text.chars().count();
+            let at = match *col {
+                0 => width.saturating_sub(len + 1),
+                c if c == n + 1 => width + offsets[n - 1] + 4,
+                c => width + offsets[c - 1] + (len < 2) as usize,
+            };
+            put(at, text);
+        }
+        line.into_iter().collect::<String>().trim_end().to_owned()
+    }
+
+    /// print - write grid to stdout
+    pub fn print(&self, write_header:bool) {
+        // calculate solved cell count
+        let mut used: u32 = 0;
+        let total: usize = self.cells.len();
+        for i in 0..total {
+            if self.cells[i].solved {
+                used += 1;
+            }
+        }
+
+        // write out header
+        if write_header {
+            println!("{} {}/{}", self.name, used, total);
+        }
+
+        // write out cells, with box spacing or the outlines of cages or jigsaw regions
+        // (or, for a Futoshiki, the signs between cells)
+        let (bw, bh) = self.box_lines();
+        let n = self.states as usize;
+        let groups = self.outline();
+        let signs = self.is_latin() && groups.is_empty()
+            && self.constraints.iter().any(|c| matches!(c, Constraint::Pair { rule: PairRule::Greater, .. }));
+
+        // outside clues go in a margin around the cells, over or beside their row or column
+        let ring = self.ring_clues();
+        let width = ring.iter().filter(|((_, col), _)| *col == 0).map(|(_, t)| t.chars().count() + 1).max()
+            .unwrap_or(if ring.is_empty() { 0 } else { 2 });
+        let margin = " ".repeat(width);
+        let offsets: Vec<usize> = (0..n)
+            .map(|col| 3 * col + if signs || !groups.is_empty() { col } else { 3 * (col / bw) })
+            .collect();
+        let clue = |row: usize, col: usize| {
+            let text = ring.iter().find(|(at, _)| *at == (row, col)).map_or("", |(_, t)| t.as_str());
+            if col == 0 { format!("{:>1$}", text, width.saturating_sub(1)) + if width == 0 { "" } else { " " } }
+            else if text.is_empty() { String::new() } else { format!(" {}", text) }
+        };
+        if !ring.is_empty() {
+            println!("{}", Grid::ring_edge(&ring, 0, &offsets, width));
+        }
+
+        if self.constraints.iter().any(|c| c.is_cage()) {
+            println!("{}{}", margin, self.region_boundary(&groups, 0));
+        }
+        for i in 0..total {
+            if i % n == 0 {
+                if i != 0 {
+                    println!("{}", clue(i / n, n + 1));
+                    if !groups.is_empty() {
+                        println!("{}{}", margin, self.region_boundary(&groups, i / n));
+                    } else if signs {
+                        let line: Vec<String> = (i - n..i).map(|a| format!(" {} ", self.sign(a, a + n, 'v', '^'))).collect();
+                        println!("{}{}", margin, line.join(" ").trim_end());
+                    } else if i % (bh * n) == 0 {
+                        println!();
+                    }
+                }
+                print!("{}", clue(i / n + 1, 0));
+            } else if signs {
+                print!("{}", self.sign(i - 1, i, '>', '<'));
+            } else if !groups.is_empty() {
+                print!("{}", if groups[i] != groups[i - 1] { "|" } else { " " });
+            } else if i % bw == 0 {
+                print!("   ");
+            }
+
+            if self.cells[i].solved {
+                // trying symbols rather than .chars().nth()  [still messy]
+                //print!(" {} ", self.state_dict.chars().nth(self.cells[i].solution as usize).unwrap());
+                let sym = format!("{}", self.symbols[(self.cells[i].solution) as usize]);
+                match self.cells[i].highlight {
+                1 => print!(" {} ", sym.green().bold()),
+                2 => print!(" {} ", sym.red().bold()),
+                _ => print!(" {} ", sym),
+                }
+                //print!(" {} ", sym.green().bold());
+                //print!(" {} ", sym);
+            } else {
+                print!(" - ");
+            }
+        }
+        println!("{}", clue(n, n + 1));
+        if !ring.is_empty() {
+            println!("{}", Grid::ring_edge(&ring, n + 1, &offsets, width));
+        }
+    }
+
+    /// print_candidates - write grid to stdout with the possible states of unsolved cells
+    pub fn print_candidates(&mut self) {
+        self.update_possible();
+        let n = self.states as usize;
+        let (bw, bh) = self.box_lines();
+
+        println!("{}", self);
+        for row in 0..n {
+            if row != 0 && row % bh == 0 {
+                println!();
+            }
+            for col in 0..n {
+                if col != 0 && col % bw == 0 {
+                    print!("  ");
+                }
+                let cell = &self.cells[row * n + col];
+                if cell.solved {
+                    let sym = format!("{:^w$}", self.symbols[cell.solution as usize], w = n);
+                    match cell.highlight {
+                    1 => print!(" {}", sym.green().bold()),
+                    2 => print!(" {}", sym.red().bold()),
+                    _ => print!(" {}", sym.bold()),
+                    }
+                } else {
+                    let text: String = (0..n).filter(|&s| cell.possible[s]).map(|s| self.symbols[s]).collect();
+                    print!(" {:<w$}", text, w = n);
+                }
+            }
+            println!();
+        }
+    }
+
+    /// clear_rc - make the cell at (row,col) unsolved again
+    /// earlier eliminations may have relied on it, so they are forgotten too
+    pub fn clear_rc(&mut self, row: usize, col: usize) {
+        let address = row * self.states as usize + col;
+        self.cells[address].solved = false;
+        self.cells[address].given = false;
+        self.cells[address].highlight = 0;
+        for cell in self.cells.iter_mut() {
+            cell.disallowed.fill(false);
+        }
+    }
+
+    /// tab - write grid to stdout in pencil marks (see tab_text)
+    pub fn tab(&mut self, last: Option<&Step>) {
+        print!("{}", self.tab_text(last));
+    }
+
+    /// tab_text - grid in pencil marks: each unsolved cell as a mini-grid of its
+    /// candidates (about isqrt by isqrt, '.' for states it can't take) and each solved
+    /// cell its value in the middle, with box separators - the eliminations of last, the
+    /// step just applied, are shown in red
+    pub fn tab_text(&mut self, last: Option<&Step>) -> String {
+        self.update_possible();
+        let n = self.states as usize;
+        let used = self.cells.iter().filter(|c| c.solved).count();
+        let mut out = format!("{} {}/{}\n", self.name, used, self.size);
+
+        // mini-grids w states wide and h high, enough for every state
+        let w = (1..=n).find(|w| w * w >= n).unwrap_or(1);
+        let h = n.div_ceil(w);
+        let middle = (h / 2) * w + w / 2;
+        let eliminated = |address: usize, s: usize| last.is_some_and(|step| step.eliminated.contains(&(address, s as Snumb)));
+
+        let (bw, bh) = self.box_lines();
+        // what goes before the cell in col, inside a box or at the start of one
+        let gap = |col: usize, inside: &'static str, between: &'static str| match col {
+            0 => "",
+            _ if col.is_multiple_of(bw) => between,
+            _ => inside,
+        };
+        let rule: String = (0..n).map(|col| gap(col, "-", "-+-").to_owned() + &"-".repeat(w)).collect();
+        let spacer: String = (0..n).map(|col| gap(col, " ", " | ").to_owned() + &" ".repeat(w)).collect();
+        for row in 0..n {
+            if row != 0 {
+                out.push_str(if row % bh == 0 { &rule } else { spacer.trim_end() });
+                out.push('\n');
+            }
+            for line in 0..h {
+                let mut text = String::new();
+                for col in 0..n {
+                    text.push_str(gap(col, " ", " | "));
+                    let address = row * n + col;
+                    let cell = &self.cells[address];
+                    for s in line * w..(line + 1) * w {
+                        let mark = if cell.solved {
+                            if s != middle {
+                                " ".to_owned()
+                            } else {
+                                let sym = self.symbols[cell.solution as usize].to_string();
+                                match cell.highlight {
+                                    1 => sym.green().bold().to_string(),
+                                    2 => sym.red().bold().to_string(),
+                                    _ => sym.bold().to_string(),
+                                }
+                            }
+                        } else if s >= n {
+                            " ".to_owned()
+                        } else if cell.possible[s] {
+                            self.symbols[s].to_string()
+                        } else if eliminated(address, s) {
+                            self.symbols[s].to_string().red().to_string()
+                        } else {
+                            ".".to_owned()
+                        };
+                        text.push_str(&mark);
+                    }
+                }
+                out.push_str(text.trim_end());
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+// Implement logical solving
+impl Grid {
+    /// houses - cell addresses of each row, then each column, then each block
+    /// (the units of the unit table)
+    pub fn houses(&self) -> &[Vec<usize>] {
+        &self.units.cells
+    }
+
+    /// house_name - describe house h (as numbered by houses) for messages
+    pub fn house_name(&self, h: usize) -> String {
+        self.units.kinds[h].to_string()
+    }
+
+    /// unit_name - describe a unit for messages, blocks with the rows and columns they cover
+    pub fn unit_name(&self, unit: Unit) -> String {
+        match unit {
+            Unit::Block(b) if self.box_w != 0 => {
+                let (bw, bh) = (self.box_w as usize, self.box_h as usize);
+                let stacks = self.states as usize / bw;
+                let (r, c) = ((b / stacks) * bh + 1, (b % stacks) * bw + 1);
+                format!("{} (r{}-{}c{}-{})", unit, r, r + bh - 1, c, c + bw - 1)
+            }
+            Unit::Window(_) => {
+                let u = self.units.kinds.iter().position(|&k| k == unit).unwrap_or(0);
+                let (first, last) = (self.units.cells[u][0], self.units.cells[u][self.units.cells[u].len() - 1]);
+                let n = self.states as usize;
+                format!("{} (r{}-{}c{}-{})", unit, first / n + 1, last / n + 1, first % n + 1, last % n + 1)
+            }
+            Unit::Knight(a) | Unit::King(a) | Unit::Neighbours(a) => format!("{} from {}", unit.kind(), self.cell_name(a)),
+            _ => unit.to_string(),
+        }
+    }
+
+    /// constraint_name - describe constraint k for messages, numbered among those of its
+    /// kind, e.g. "cage 3"
+    pub fn constraint_name(&self, k: usize) -> String {
+        constraint::name(&self.constraints, k)
+    }
+
+    /// cell_name - describe address as `r<row>c<col>` for messages
+    pub fn cell_name(&self, address: usize) -> String {
+        let n = self.states as usize;
+        format!("r{}c{}", address / n + 1, address % n + 1)
+    }
+
+    /// update_possible - recompute the possible states of each cell from the solved cells
+    /// in its row, column and block (and chess moves and neighbours, for those variants) and
+    /// what its constraints allow, less anything marked disallowed by a technique
+    pub fn update_possible(&mut self) {
+        let n = self.states as usize;
+        let units = Arc::clone(&self.units);
+        let mut used = vec![0u64; units.cells.len()];
+        let mut masks = Vec::with_capacity(n);
+        for (h, house) in units.cells.iter().enumerate() {
+            masks.clear();
+            masks.extend(house.iter().map(|&address| {
+                let cell = &self.cells[address];
+                if cell.solved { 1u64 << cell.solution } else { 0 }
+            }));
+            used[h] = kernel::unit_scan(&masks);
+        }
+
+        let values = self.values();
+        for address in 0..self.size {
+            let mut taken = units.of_cell[address].iter().fold(0, |taken, &u| taken | used[u]);
+            for &other in &units.moves[address] {
+                if values[other] != 0 {
+                    taken |= 1u64 << (values[other] - 1);
+                }
+            }
+            for &other in &units.neighbours[address] {
+                if values[other] != 0 {
+                    taken |= consecutive(values[other]);
+                }
+            }
+            for constraint in self.constraints.iter().filter(|c| c.cells().contains(&address)) {
+                taken |= !constraint.allowed(address, &values, n);
+            }
+            let cell = &mut self.cells[address];
+            for s in 0..n {
+                cell.possible[s] = if cell.solved {
+                    s == cell.solution as usize
+                } else {
+                    taken & (1u64 << s) == 0 && !cell.disallowed[s]
+                };
+            }
+        }
+    }
+
+    /// candidates - bitmask of the possible states of each cell (0 for solved cells)
+    pub fn candidates(&self) -> Vec<u64> {
+        self.cells.iter()
+            .map(|cell| {
+                if cell.solved {
+                    return 0;
+                }
+                cell.possible.iter().enumerate()
+                    .filter(|&(_, &p)| p)
+                    .fold(0u64, |mask, (s, _)| mask | (1u64 << s))
+            })
+            .collect()
+    }
+
+    /// find_step - look for a deduction using one technique
+    pub fn find_step(&mut self, technique: Technique) -> Option<Step> {
+        self.update_possible();
+        let cands = self.candidates();
+        let houses = &self.units.cells;
+        match technique {
+            Technique::FullHouse => self.find_full_house(&cands, houses),
+            Technique::NakedSingle => self.find_naked_single(&cands),
+            Technique::HiddenSingle => self.find_hidden_single(&cands, houses),
+            Technique::InniesOuties => self.find_innies_outies(&cands, houses),
+            Technique::CageSum | Technique::CageArithmetic | Technique::Thermometer | Technique::ArrowSum
+            | Technique::SandwichSum | Technique::Skyscraper | Technique::XSum | Technique::LittleKiller
+            | Technique::Dots | Technique::GreaterThan => self.find_constraint_step(technique, &cands),
+            Technique::LockedCandidates => self.find_locked_candidates(&cands, houses),
+            Technique::NakedPair => self.find_naked_subset(&cands, houses, 2),
+            Technique::HiddenPair => self.find_hidden_pair(&cands, houses),
+            Technique::NakedTriple => self.find_naked_subset(&cands, houses, 3),
+            Technique::XWing => self.find_x_wing(&cands, houses),
+        }
+    }
+
+    /// next_step - find a deduction using the easiest technique that has one
+    pub fn next_step(&mut self) -> Option<Step> {
+        for technique in TECHNIQUES {
+            if let Some(step) = self.find_step(technique) {
+                return Some(step);
+            }
+        }
+        None
+    }
+
+    /// apply_step - make the claims and eliminations of a step, highlighting the claims
+    pub fn apply_step(&mut self, step: &Step) -> Result<(), GridError> {
+        for cell in self.cells.iter_mut() {
+            cell.highlight = 0;
+        }
+        for &(address, state) in &step.placed {
+            self.claim_a(address, state)?;
+        }
+        for &(address, state) in &step.eliminated {
+            self.cells[address].disallowed[state as usize] = true;
+        }
+        self.update_possible();
+        Ok(())
+    }
+
+    /// solve_logic - keep applying the easiest step until stuck, returning the steps
+    pub fn solve_logic(&mut self) -> Result<Vec<Step>, GridError> {
+        let mut steps = Vec::new();
+        while let Some(step) = self.next_step() {
+            self.apply_step(&step)?;
+            steps.push(step);
+        }
+        Ok(steps)
+    }
+
+    /// rate - grade the puzzle by logically solving a copy of it
+    pub fn rate(&self) -> Result<Rating, GridError> {
+        Ok(self.rate_with_steps()?.0)
+    }
+
+    /// rate_with_steps - rate, also returning the steps taken
+    pub fn rate_with_steps(&self) -> Result<(Rating, Vec<Step>), GridError> {
+        let mut g = self.clone();
+        let steps = g.solve_logic()?;
+        let rating = Rating {
+            hardest: steps.iter().map(|s| s.technique).max_by_key(|t| t.weight()),
+            steps: steps.len(),
+            score: steps.iter().map(|s| s.technique.weight()).sum(),
+            solved: g.cells.iter().all(|c| c.solved),
+            uses: TECHNIQUES.iter().map(|&t| steps.iter().filter(|s| s.technique == t).count()).collect(),
+        };
+        Ok((rating, steps))
+    }
+
+    // placement - step claiming a single cell
+    fn placement(&self, technique: Technique, address: usize, state: Snumb, why: String) -> Step {
+        Step {
+            technique,
+            placed: vec![(address, state)],
+            eliminated: Vec::new(),
+            reason: format!("{} at {}: {}", self.symbols[state as usize], self.cell_name(address), why),
+        }
+    }
+
+    fn find_full_house(&self, cands: &[u64], houses: &[Vec<usize>]) -> Option<Step> {
+        for (h, house) in houses.iter().enumerate() {
+            let free: Vec<usize> = house.iter().copied().filter(|&a| !self.cells[a].solved).collect();
+            if free.len() == 1 && cands[free[0]].count_ones() == 1 {
+                let state = cands[free[0]].trailing_zeros() as Snumb;
+                let why = format!("last free cell of {}", self.house_name(h));
+                return Some(self.placement(Technique::FullHouse, free[0], state, why));
+            }
+        }
+        None
+    }
+
+    fn find_naked_single(&self, cands: &[u64]) -> Option<Step> {
+        let address = (0..self.size).find(|&a| cands[a].count_ones() == 1)?;
+        let state = cands[address].trailing_zeros() as Snumb;
+        Some(self.placement(Technique::NakedSingle, address, state, "only candidate left".to_owned()))
+    }
+
+    fn find_hidden_single(&self, cands: &[u64], houses: &[Vec<usize>]) -> Option<Step> {
+        for (h, house) in houses.iter().enumerate() {
+            for state in 0..self.states {
+                let bit = 1u64 << state;
+                let mut places = house.iter().filter(|&&a| cands[a] & bit != 0);
+                if let (Some(&address), None) = (places.next(), places.next()) {
+                    let why = format!("only place for it in {}", self.house_name(h));
+                    return Some(self.placement(Technique::HiddenSingle, address, state, why));
+                }
+            }
+        }
+        None
+    }
+
+    // find_innies_outies - every unit adds up to 1+2+..+n, so once the cages wholly inside
+    // it are taken off, a single cell left over (an innie) makes up the difference - and
+    // likewise when cages cover the unit, a single cell sticking out (an outie) makes what
+    // they add up to beyond the unit
+    fn find_innies_outies(&self, cands: &[u64], houses: &[Vec<usize>]) -> Option<Step> {
+        let n = self.states as usize;
+        let total = (n * (n + 1) / 2) as i64;
+        let values = self.values();
+        let cages: Vec<(i64, &[usize])> = self.constraints.iter()
+            .filter_map(|c| c.cage_sum().map(|sum| (sum as i64, c.cells())))
+            .collect();
+        if cages.is_empty() {
+            return None;
+        }
+        let solved = |cells: &[usize]| cells.iter().map(|&a| values[a] as i64).sum::<i64>();
+
+        for (h, house) in houses.iter().enumerate() {
+            let inside: Vec<&(i64, &[usize])> = cages.iter().filter(|c| c.1.iter().all(|a| house.contains(a))).collect();
+            let rest: Vec<usize> = house.iter().copied().filter(|a| !inside.iter().any(|c| c.1.contains(a))).collect();
+            let open: Vec<usize> = rest.iter().copied().filter(|&a| values[a] == 0).collect();
+            if let [address] = open[..] {
+                let caged = inside.iter().map(|c| c.0).sum::<i64>();
+                let why = format!("innie of {}, whose cages add up to {}", self.house_name(h), caged);
+                if let Some(step) = self.sum_placement(cands, address, total - caged - solved(&rest), why) {
+                    return Some(step);
+                }
+            }
+
+            let over: Vec<&(i64, &[usize])> = cages.iter().filter(|c| c.1.iter().any(|a| house.contains(a))).collect();
+            if house.iter().all(|a| over.iter().any(|c| c.1.contains(a))) {
+                let outside: Vec<usize> = over.iter().flat_map(|c| c.1.iter().copied()).filter(|a| !house.contains(a)).collect();
+                let open: Vec<usize> = outside.iter().copied().filter(|&a| values[a] == 0).collect();
+                if let [address] = open[..] {
+                    let caged = over.iter().map(|c| c.0).sum::<i64>();
+                    let why = format!("outie of {}, whose covering cages add up to {}", self.house_name(h), caged);
+                    if let Some(step) = self.sum_placement(cands, address, caged - total - solved(&outside), why) {
+                        return Some(step);
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // sum_placement - step claiming a cell for the state counting as value, if a candidate
+    fn sum_placement(&self, cands: &[u64], address: usize, value: i64, why: String) -> Option<Step> {
+        if value < 1 || value > self.states as i64 || cands[address] & (1u64 << (value - 1)) == 0 {
+            return None;
+        }
+        Some(self.placement(Technique::InniesOuties, address, (value - 1) as Snumb, why))
+    }
+
+    // find_constraint_step - candidates which a constraint worked on by the technique
+    // rules out (e.g. in no combination of different states making a cage's sum)
+    fn find_constraint_step(&self, technique: Technique, cands: &[u64]) -> Option<Step> {
+        let values = self.values();
+        for (k, constraint) in self.constraints.iter().enumerate() {
+            if constraint.technique() != Some(technique) {
+                continue;
+            }
+            let eliminated = constraint.eliminate(cands, &values, self.states as usize);
+            if !eliminated.is_empty() {
+                let mask = eliminated.iter().fold(0u64, |m, &(_, s)| m | 1u64 << s);
+                // (for the lack of dots, just the cells losing candidates)
+                let mut shown: Vec<usize> = match constraint {
+                    Constraint::Negative { .. } => eliminated.iter().map(|&(a, _)| a).collect(),
+                    _ => constraint.cells().to_vec(),
+                };
+                shown.dedup();
+                let names: Vec<String> = shown.iter().map(|&a| self.cell_name(a)).collect();
+                return Some(Step {
+                    technique,
+                    placed: Vec::new(),
+                    reason: format!("no way of {} in {} ({}) uses {}, so it is removed", constraint.rule(),
+                                    self.constraint_name(k), names.join(","), self.mask_symbols(mask)),
+                    eliminated,
+                });
+            }
+        }
+        None
+    }
+
+    // find_locked_candidates - a state confined to where a block meets a row or column
+    // can be removed from the rest of the row/column (pointing) or the block (claiming)
+    // (blocks here being any unit other than a row or column, crossed by any other unit)
+    fn find_locked_candidates(&self, cands: &[u64], houses: &[Vec<usize>]) -> Option<Step> {
+        let units = &self.units;
+        for block in (0..units.kinds.len()).filter(|&u| !matches!(units.kinds[u], Unit::Row(_) | Unit::Col(_))) {
+            // the units crossing this block
+            let mut lines: Vec<usize> = houses[block].iter()
+                .flat_map(|&a| units.of_cell[a].iter().copied())
+                .filter(|&u| u != block)
+                .collect();
+            lines.sort_unstable();
+            lines.dedup();
+            for line in lines {
+                let inter: Vec<usize> = houses[block].iter().copied().filter(|a| houses[line].contains(a)).collect();
+                for state in 0..self.states {
+                    let bit = 1u64 << state;
+                    for (from, to) in [(block, line), (line, block)] {
+                        let inside = inter.iter().any(|&a| cands[a] & bit != 0);
+                        let outside = houses[from].iter().any(|a| !inter.contains(a) && cands[*a] & bit != 0);
+                        if !inside || outside {
+                            continue;
+                        }
+                        let eliminated: Vec<(usize, Snumb)> = houses[to].iter()
+                            .filter(|a| !inter.contains(a) && cands[**a] & bit != 0)
+                            .map(|&a| (a, state))
+                            .collect();
+                        if !eliminated.is_empty() {
+                            return Some(Step {
+                                technique: Technique::LockedCandidates,
+                                placed: Vec::new(),
+                                eliminated,
+                                reason: format!("{} in {} is confined to {}, so is removed from the rest of {}",
+                                                self.symbols[state as usize], self.house_name(from),
+                                                self.house_name(to), self.house_name(to)),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // find_naked_subset - k cells of a house with only k candidates between them, which
+    // can then be removed from the other cells of the house
+    fn find_naked_subset(&self, cands: &[u64], houses: &[Vec<usize>], k: usize) -> Option<Step> {
+        let technique = if k == 2 { Technique::NakedPair } else { Technique::NakedTriple };
+        for (h, house) in houses.iter().enumerate() {
+            let small: Vec<usize> = house.iter().copied()
+                .filter(|&a| (2..=k as u32).contains(&cands[a].count_ones()))
+                .collect();
+            for subset in combinations(&small, k) {
+                let union = subset.iter().fold(0u64, |m, &a| m | cands[a]);
+                if union.count_ones() as usize != k {
+                    continue;
+                }
+                let mut eliminated = Vec::new();
+                for &a in house {
+                    if !subset.contains(&a) {
+                        for state in 0..self.states {
+                            if cands[a] & union & (1u64 << state) != 0 {
+                                eliminated.push((a, state));
+                            }
+                        }
+                    }
+                }
+                if !eliminated.is_empty() {
+                    let cells: Vec<String> = subset.iter().map(|&a| self.cell_name(a)).collect();
+                    return Some(Step {
+                        technique,
+                        placed: Vec::new(),
+                        eliminated,
+                        reason: format!("{} in {} hold only {}", cells.join(","), self.house_name(h),
+                                        self.mask_symbols(union)),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    // find_hidden_pair - two states with the same two places in a house, so those two
+    // cells can't hold anything else
+    fn find_hidden_pair(&self, cands: &[u64], houses: &[Vec<usize>]) -> Option<Step> {
+        for (h, house) in houses.iter().enumerate() {
+            let places: Vec<Vec<usize>> = (0..self.states)
+                .map(|s| house.iter().copied().filter(|&a| cands[a] & (1u64 << s) != 0).collect())
+                .collect();
+            for s1 in 0..self.states as usize {
+                for s2 in s1 + 1..self.states as usize {
+                    if places[s1].len() != 2 || places[s1] != places[s2] {
+                        continue;
+                    }
+                    let pair = (1u64 << s1) | (1u64 << s2);
+                    let mut eliminated = Vec::new();
+                    for &a in &places[s1] {
+                        for state in 0..self.states {
+                            if cands[a] & !pair & (1u64 << state) != 0 {
+                                eliminated.push((a, state));
+                            }
+                        }
+                    }
+                    if !eliminated.is_empty() {
+                        return Some(Step {
+                            technique: Technique::HiddenPair,
+                            placed: Vec::new(),
+                            eliminated,
+                            reason: format!("{} in {} can only go in {},{}", self.mask_symbols(pair),
+                                            self.house_name(h), self.cell_name(places[s1][0]),
+                                            self.cell_name(places[s1][1])),
+                        });
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // find_x_wing - a state with the same two places in each of two rows can be removed
+    // from the rest of those two columns (and the same with rows and columns swapped)
+    fn find_x_wing(&self, cands: &[u64], houses: &[Vec<usize>]) -> Option<Step> {
+        let n = self.states as usize;
+        for state in 0..self.states {
+            let bit = 1u64 << state;
+            for base in [0, n] {
+                // crossing index (column for rows, row for columns) of each place
+                let cross = |a: usize| if base == 0 { a % n } else { a / n };
+                let places: Vec<Vec<usize>> = (base..base + n)
+                    .map(|h| houses[h].iter().copied().filter(|&a| cands[a] & bit != 0).map(cross).collect())
+                    .collect();
+                for l1 in 0..n {
+                    for l2 in l1 + 1..n {
+                        if places[l1].len() != 2 || places[l1] != places[l2] {
+                            continue;
+                        }
+                        let covers = [n - base + places[l1][0], n - base + places[l1][1]];
+                        let mut eliminated = Vec::new();
+                        for cover in covers {
+                            for &a in &houses[cover] {
+                                let line = if base == 0 { a / n } else { a % n };
+                                if line != l1 && line != l2 && cands[a] & bit != 0 {
+                                    eliminated.push((a, state));
+                                }
+                            }
+                        }
+                        if !eliminated.is_empty() {
+                            return Some(Step {
+                                technique: Technique::XWing,
+                                placed: Vec::new(),
+                                eliminated,
+                                reason: format!("{} in {} and {} is confined to {} and {}",
+                                                self.symbols[state as usize], self.house_name(base + l1),
+                                                self.house_name(base + l2), self.house_name(covers[0]),
+                                                self.house_name(covers[1])),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // mask_symbols - the symbols of the states in a bitmask
+    fn mask_symbols(&self, mask: u64) -> String {
+        (0..self.states as usize).filter(|&s| mask & (1u64 << s) != 0).map(|s| self.symbols[s]).collect()
+    }
+}
+
+/// Search is a bitmask working copy of a grid used for brute-force solving
+// each unit holds a u64 of the states already used, so up to 64 states
+pub(crate) struct Search {
+    n: usize,                    // number of states
+    values: Vec<u8>,             // 0 if unsolved, otherwise solution+1
+    used: Vec<u64>,              // states used per unit
+    pub(crate) limit: usize,     // stop once this many solutions found
+    pub(crate) solutions: usize, // number of solutions found so far
+    pub(crate) first: Vec<u8>,   // values of the first solution found
+    pub(crate) random: Option<Rng>, // if set, try states in random order (for generating)
+    units: Arc<Units>,           // unit table of the grid
+    constraints: Vec<Constraint>, // rules on particular cells
+    of_cell: Vec<Vec<usize>>,    // constraints on each cell (positions in constraints)
+}
+
+impl Search {
+    // from_grid - set up search from the solved cells of a grid (a Contradiction if they conflict)
+    fn from_grid(g: &Grid) -> Result<Search, GridError> {
+        let values: Vec<Option<Snumb>> = g.cells.iter().map(|c| c.solved.then_some(c.solution)).collect();
+        Search::from_values(g.states as usize, &g.units, &g.constraints, &values, &g.symbols)
+    }
+
+    // from_values - set up search for n states over a unit table and constraints, from the
+    // value of each cell (None if unsolved) - a Contradiction if they conflict, or
+    // Unsatisfied if they break a constraint
+    pub(crate) fn from_values(n: usize, units: &Arc<Units>, constraints: &[Constraint], values: &[Option<Snumb>],
+                              symbols: &[char]) -> Result<Search, GridError> {
+        let mut of_cell = vec![Vec::new(); values.len()];
+        for (k, constraint) in constraints.iter().enumerate() {
+            for &a in constraint.cells() {
+                if of_cell[a].last() != Some(&k) {
+                    of_cell[a].push(k);
+                }
+            }
+        }
+        let mut s = Search {
+            n,
+            values: vec![0; values.len()],
+            used: vec![0; units.cells.len()],
+            limit: 1,
+            solutions: 0,
+            first: Vec::new(),
+            random: None,
+            units: Arc::clone(units),
+            constraints: constraints.to_vec(),
+            of_cell,
+        };
+
+        let unbatched = vec![None; constraints.len()];
+        for (address, value) in values.iter().enumerate() {
+            if let &Some(state) = value {
+                let bit = 1u64 << state;
+                if s.free(address, &unbatched) & bit == 0 {
+                    if let Some(u) = s.units.of_cell[address].iter().copied().find(|&u| s.used[u] & bit != 0) {
+                        return Err(GridError::Contradiction { unit: s.units.kinds[u], state: symbols[state as usize] });
+                    }
+                    if let Some(&a) = s.units.moves[address].iter().find(|&&a| s.values[a] == state + 1) {
+                        let rule = match move_unit(a, address, n) { Unit::King(_) => "anti-king", _ => "anti-knight" };
+                        return Err(GridError::Unsatisfied(rule.to_owned()));
+                    }
+                    if s.units.neighbours[address].iter().any(|&a| s.values[a] != 0 && consecutive(s.values[a]) & bit != 0) {
+                        return Err(GridError::Unsatisfied("non-consecutive".to_owned()));
+                    }
+                    let k = s.of_cell[address].iter().copied()
+                        .find(|&k| s.constraints[k].allowed(address, &s.values, n) & bit == 0).unwrap_or(0);
+                    return Err(GridError::Unsatisfied(constraint::name(constraints, k)));
+                }
+                s.place(address, state as usize);
+            }
+        }
+        Ok(s)
+    }
+
+    // free - bitmask of states still possible at address, taking what constraints allow
+    // from batched where they worked it out for all their cells at once (see allowed_all)
+    fn free(&self, address: usize, batched: &[Option<Vec<u64>>]) -> u64 {
+        self.of_cell[address].iter().fold(self.unit_free(address), |free, &k| {
+            let constraint = &self.constraints[k];
+            free & match &batched[k] {
+                Some(masks) => constraint.cells().iter().position(|&a| a == address).map_or(0, |p| masks[p]),
+                None => constraint.allowed(address, &self.values, self.n),
+            }
+        })
+    }
+
+    // unit_free - bitmask of states not used by the peers of address
+    fn unit_free(&self, address: usize) -> u64 {
+        let all = if self.n == 64 { u64::MAX } else { (1u64 << self.n) - 1 };
+        let mut used = self.units.of_cell[address].iter().fold(0, |used, &u| used | self.used[u]);
+        for &other in &self.units.moves[address] {
+            if self.values[other] != 0 {
+                used |= 1u64 << (self.values[other] - 1);
+            }
+        }
+        for &other in &self.units.neighbours[address] {
+            if self.values[other] != 0 {
+                used |= consecutive(self.values[other]);
+            }
+        }
+        all & !used
+    }
+
+    fn place(&mut self, address: usize, state: usize) {
+        let bit = 1u64 << state;
+        self.values[address] = state as u8 + 1;
+        for &u in &self.units.of_cell[address] {
+            self.used[u] |= bit;
+        }
+    }
+
+    fn unplace(&mut self, address: usize, state: usize) {
+        let bit = 1u64 << state;
+        self.values[address] = 0;
+        for &u in &self.units.of_cell[address] {
+            self.used[u] &= !bit;
+        }
+    }
+
+    // run - depth-first search, always branching on the cell with fewest options
+    pub(crate) fn run(&mut self) {
+        if self.solutions >= self.limit {
+            return;
+        }
+
+        // branch on the cell with fewest options
+        let mut free = vec![0u64; self.values.len()];
+        let batched: Vec<Option<Vec<u64>>> = self.constraints.iter().map(|c| c.allowed_all(&self.values, self.n)).collect();
+        let mut best: Option<(usize, u64)> = None;
+        for (address, &value) in self.values.iter().enumerate() {
+            if value != 0 {
+                continue;
+            }
+            let options = self.free(address, &batched);
+            free[address] = options;
+            if options == 0 {
+                return; // dead end
+            }
+            if best.is_none_or(|(_, b)| options.count_ones() < b.count_ones()) {
+                best = Some((address, options));
+                if options.count_ones() == 1 {
+                    break;
+                }
+            }
+        }
+
+        let Some((address, mut options)) = best else {
+            // no unsolved cells left
+            if self.solutions == 0 {
+                self.first = self.values.clone();
+            }
+            self.solutions += 1;
+            return;
+        };
+
+        // ... unless some state has fewer places left in a unit
+        // (when not generating, as that wants states tried in random order)
+        if options.count_ones() > 1 && self.random.is_none() {
+            let mut fewest: Option<(usize, Vec<usize>)> = None;
+            for house in &self.units.cells {
+                let mut missing = house.iter().fold(0u64, |m, &a| m | free[a]);
+                let placed = house.iter().filter(|&&a| self.values[a] != 0).count();
+                if (missing.count_ones() as usize) < self.n - placed {
+                    return; // some state has nowhere to go
+                }
+                while missing != 0 {
+                    let state = missing.trailing_zeros() as usize;
+                    missing &= missing - 1;
+                    let places: Vec<usize> = house.iter().copied().filter(|&a| free[a] & (1u64 << state) != 0).collect();
+                    if places.len() < fewest.as_ref().map_or(options.count_ones() as usize, |f| f.1.len()) {
+                        fewest = Some((state, places));
+                    }
+                }
+            }
+            if let Some((state, places)) = fewest {
+                for address in places {
+                    self.place(address, state);
+                    self.run();
+                    self.unplace(address, state);
+                    if self.solutions >= self.limit {
+                        return;
+                    }
+                }
+                return;
+            }
+        }
+
+        while options != 0 {
+            // lowest remaining state, or a random one when generating
+            let mut pick = options;
+            if let Some(rng) = self.random.as_mut() {
+                for _ in 0..rng.below(options.count_ones() as usize) {
+                    pick &= pick - 1;
+                }
+            }
+            let state = pick.trailing_zeros() as usize;
+            options &= !(1u64 << state);
+            self.place(address, state);
+            self.run();
+            self.unplace(address, state);
+            if self.solutions >= self.limit {
+                return;
+            }
+        }
+    }
+}
+
+/// Json is a minimal JSON value, enough for the --json output of the commands
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(f64),
+    Str(String),
+    Arr(Vec<Json>),
+    Obj(Vec<(&'static str, Json)>), // fields in output order
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Num(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Num(_) => write!(f, "null"),
+            Json::Str(s) => json_string(f, s),
+            Json::Arr(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Obj(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// json_string - write s as a quoted JSON string
+fn json_string(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl Json {
+    pub fn num(n: usize) -> Json {
+        Json::Num(n as f64)
+    }
+
+    pub fn str(s: impl Into<String>) -> Json {
+        Json::Str(s.into())
+    }
+}
+
+impl Grid {
+    /// to_json - the grid with status and, for each cell in reading order, whether it
+    /// is given or solved, its value and its remaining candidates
+    pub fn to_json(&self) -> Json {
+        let n = self.states as usize;
+        let mut g = self.clone();
+        g.update_possible();
+        let cells = g.cells.iter().enumerate().map(|(address, c)| {
+            let candidates = if c.solved {
+                Vec::new()
+            } else {
+                (0..n).filter(|&s| c.possible[s]).map(|s| Json::str(self.symbols[s])).collect()
+            };
+            Json::Obj(vec![
+                ("row", Json::num(address / n + 1)),
+                ("col", Json::num(address % n + 1)),
+                ("given", Json::Bool(c.given)),
+                ("solved", Json::Bool(c.solved)),
+                ("value", if c.solved { Json::str(self.symbols[c.solution as usize]) } else { Json::Null }),
+                ("candidates", Json::Arr(candidates)),
+            ])
+        }).collect();
+        Json::Obj(vec![
+            ("name", Json::str(self.name.as_str())),
+            ("state_dict", Json::str(self.state_dict.as_str())),
+            ("states", Json::num(n)),
+            ("isqrt", Json::num(self.isqrt as usize)),
+            ("box_width", Json::num(self.box_w as usize)),
+            ("box_height", Json::num(self.box_h as usize)),
+            ("regions", Json::Arr(self.regions.iter().map(|&r| Json::num(r + 1)).collect())),
+            ("variants", Json::Arr(self.variants.iter().map(|v| Json::str(v.name())).collect())),
+            ("constraints", Json::Arr(self.constraints.iter().map(|c| c.to_json(n)).collect())),
+            ("status", Json::str(format!("{:?}", self.status))),
+            ("cells", Json::Arr(cells)),
+        ])
+    }
+
+    /// violation_json - a broken constraint, numbered from 1 among those of its kind
+    pub fn violation_json(&self, violation: &Violation) -> Json {
+        let n = self.states as usize;
+        let cells = violation.cells.iter().map(|&address| Json::Obj(vec![
+            ("row", Json::num(address / n + 1)),
+            ("col", Json::num(address % n + 1)),
+        ])).collect();
+        Json::Obj(vec![
+            ("constraint", Json::str(self.constraint_name(violation.constraint))),
+            ("reason", Json::str(violation.reason.as_str())),
+            ("cells", Json::Arr(cells)),
+        ])
+    }
+
+    /// conflict_json - a conflict, with the unit numbered from 1 like rows and columns
+    pub fn conflict_json(&self, conflict: &Conflict) -> Json {
+        let n = self.states as usize;
+        let cells = conflict.cells.iter().map(|&address| Json::Obj(vec![
+            ("row", Json::num(address / n + 1)),
+            ("col", Json::num(address % n + 1)),
+        ])).collect();
+        Json::Obj(vec![
+            ("unit", Json::str(conflict.unit.kind())),
+            ("index", Json::num(conflict.unit.index() + 1)),
+            ("value", Json::str(self.symbols[conflict.state as usize])),
+            ("cells", Json::Arr(cells)),
+        ])
+    }
+
+    /// step_json - a logical step, with cells as row/col/value
+    pub fn step_json(&self, step: &Step) -> Json {
+        let n = self.states as usize;
+        let list = |items: &[(usize, Snumb)]| Json::Arr(items.iter().map(|&(address, state)| {
+            Json::Obj(vec![
+                ("row", Json::num(address / n + 1)),
+                ("col", Json::num(address % n + 1)),
+                ("value", Json::str(self.symbols[state as usize])),
+            ])
+        }).collect());
+        Json::Obj(vec![
+            ("technique", Json::str(step.technique.name())),
+            ("placed", list(&step.placed)),
+            ("eliminated", list(&step.eliminated)),
+            ("reason", Json::str(step.reason.as_str())),
+        ])
+    }
+}
+
+impl Rating {
+    /// to_json - the rating, with uses as a count per technique name
+    pub fn to_json(&self) -> Json {
+        let uses = TECHNIQUES.iter().zip(&self.uses)
+            .filter(|&(_, &count)| count > 0)
+            .map(|(t, &count)| (t.name(), Json::num(count)))
+            .collect();
+        Json::Obj(vec![
+            ("grade", Json::str(self.grade())),
+            ("hardest", self.hardest.map_or(Json::Null, |t| Json::str(t.name()))),
+            ("steps", Json::num(self.steps)),
+            ("score", Json::num(self.score as usize)),
+            ("solved", Json::Bool(self.solved)),
+            ("uses", Json::Obj(uses)),
+        ])
+    }
+}
+
+/// Rng is a small xorshift random number generator, so runs can be repeated from a seed
+#[derive(Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// new - generator from a seed (0 is replaced, as xorshift would stay at 0)
+    pub fn new(seed: u64) -> Rng {
+        Rng(if seed == 0 { 0x9e3779b97f4a7c15 } else { seed })
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// below - random number in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// shuffle - put items into random order (Fisher-Yates)
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+/// default_boxes - (width, height) of the squarest boxes for n states, height being the
+/// largest divisor of n no more than its square root (None for primes, which have none)
+pub fn default_boxes(n: usize) -> Option<(usize, usize)> {
+    if n == 1 {
+        return Some((1, 1));
+    }
+    let h = (2..=n).take_while(|h| h * h <= n).filter(|h| n.is_multiple_of(*h)).last()?;
+    Some((n / h, h))
+}
+
+// Layout is what a config line gives in place of boxes
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    Boxes(usize, usize), // width and height
+    Jigsaw,              // irregular regions, from a second block
+    Latin,               // no boxes at all
+}
+
+// parse_boxes - box shape from a config token, "3" for 3x3, "3x2" for 3 wide and 2 high,
+// "jigsaw" for regions rather than boxes or "latin" for neither
+fn parse_boxes(token: &str) -> Option<Layout> {
+    match token {
+        "jigsaw" => return Some(Layout::Jigsaw),
+        "latin" => return Some(Layout::Latin),
+        _ => {}
+    }
+    match token.split_once('x') {
+        Some((w, h)) => Some(Layout::Boxes(w.parse().ok()?, h.parse().ok()?)),
+        None => token.parse().ok().map(|side| Layout::Boxes(side, side)),
+    }
+}
+
+/// Letters naming jigsaw regions in .sud files, region 0 first
+pub const REGION_LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789λπ";
+
+/// default_states - symbols for a grid of n states, following the test files
+pub fn default_states(n: usize) -> &'static str {
+    let states = if n <= 9 {
+        "123456789"
+    } else if n <= 36 {
+        "0123456789abcdefghijklmnopqrstuvwxyz"
+    } else {
+        "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyzλπ"
+    };
+    match states.char_indices().nth(n) {
+        Some((end, _)) => &states[..end],
+        None => states,
+    }
+}
+
+// move_unit - the chess move between two cells a move apart on a grid of n states, as a
+// unit numbered by the first (a row or column apart across the edges of a torus counts as one)
+fn move_unit(a: usize, b: usize, n: usize) -> Unit {
+    let (first, other) = (a.min(b), a.max(b));
+    let one = |d: usize| d == 1 || d == n - 1;
+    if one(other / n - first / n) && one((other % n).abs_diff(first % n)) {
+        Unit::King(first)
+    } else {
+        Unit::Knight(first)
+    }
+}
+
+// consecutive - bitmask of the states either side of a value (state+1), as ruled out
+// beside it by non-consecutive
+fn consecutive(value: u8) -> u64 {
+    let state = value as u64 - 1;
+    (1u64 << state << 1) | (1u64 << state >> 1)
+}
+
+// is_permutation - order holds each of 0..n exactly once
+fn is_permutation(order: &[usize], n: usize) -> bool {
+    let mut seen = vec![false; n];
+    order.len() == n && order.iter().all(|&k| k < n && !std::mem::replace(&mut seen[k], true))
+}
+
+// permutations - every ordering of 0..k
+fn permutations(k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    let mut all = Vec::new();
+    for p in permutations(k - 1) {
+        for i in 0..=p.len() {
+            let mut q = p.clone();
+            q.insert(i, k - 1);
+            all.push(q);
+        }
+    }
+    all
+}
+
+// combinations - every choice of k of the items, in order
+fn combinations(items: &[usize], k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![vec![]];
+    }
+    let mut all = Vec::new();
+    for i in 0..items.len() {
+        for mut rest in combinations(&items[i + 1..], k - 1) {
+            rest.insert(0, items[i]);
+            all.push(rest);
+        }
+    }
+    all
+}
+
+// canonical_rows - choose the remaining rows (keeping bands of bh rows together) to
+// minimise the relabelled grid, with columns already fixed by colmap
+// best holds the smallest grid so far; when a smaller row is found the rest of best is
+// reset to u8::MAX so the search carries on down to a complete grid
+#[allow(clippy::too_many_arguments)]
+fn canonical_rows(values: &[u8], n: usize, bh: usize, colmap: &[usize], used: &mut [bool],
+                  labels: &[u8], next: u8, depth: usize, band: usize, best: &mut [u8]) {
+    if depth == n {
+        return;
+    }
+
+    for r in 0..n {
+        // first row of a band may come from any unused band, otherwise stay in this band
+        let ok = if depth.is_multiple_of(bh) {
+            (0..bh).all(|k| !used[(r / bh) * bh + k])
+        } else {
+            r / bh == band && !used[r]
+        };
+        if !ok {
+            continue;
+        }
+
+        let mut lab = labels.to_vec();
+        let mut nx = next;
+        let mut row = vec![0u8; n];
+        for c in 0..n {
+            let v = values[r * n + colmap[c]] as usize;
+            if v > 0 {
+                if lab[v] == 0 {
+                    nx += 1;
+                    lab[v] = nx;
+                }
+                row[c] = lab[v];
+            }
+        }
+
+        let slot = &mut best[depth * n..(depth + 1) * n];
+        match row.as_slice().cmp(slot) {
+            std::cmp::Ordering::Greater => continue,
+            std::cmp::Ordering::Less => {
+                slot.copy_from_slice(&row);
+                best[(depth + 1) * n..].fill(u8::MAX);
+            }
+            std::cmp::Ordering::Equal => {}
+        }
+
+        used[r] = true;
+        canonical_rows(values, n, bh, colmap, used, &lab, nx, depth + 1, r / bh, best);
+        used[r] = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // sud - grid from one of the files in sudfiles
+    fn sud(name: &str) -> Grid {
+        let path = format!("{}/sudfiles/{}", env!("CARGO_MANIFEST_DIR"), name);
+        Grid::from_sud(&std::fs::read_to_string(&path).unwrap(), name).unwrap()
+    }
+
+    #[test]
+    fn canonical_hash_survives_transformations() {
+        let g = sud("test1a.sud");
+        let hash = g.canonical_hash().unwrap();
+        let transforms: [fn(&mut Grid); 6] = [
+            |g| g.relabel(&[3, 7, 1, 0, 8, 2, 6, 5, 4]).unwrap(),
+            |g| g.permute_rows(1, &[2, 0, 1]).unwrap(),
+            |g| g.permute_bands(&[2, 1, 0]).unwrap(),
+            |g| g.transpose().unwrap(),
+            |g| g.rotate().unwrap(),
+            |g| g.reflect().unwrap(),
+        ];
+        for transform in transforms {
+            let mut h = g.clone();
+            transform(&mut h);
+            assert_ne!(h.to_line(), g.to_line());
+            assert_eq!(h.canonical_hash().unwrap(), hash);
+        }
+        assert_ne!(sud("test2a.sud").canonical_hash().unwrap(), hash);
+    }
+
+    #[test]
+    fn canonical_refuses_what_it_cannot_reduce() {
+        assert_eq!(sud("jigsaw1.sud").canonical_hash(), Err(GridError::NotForLayout("canonical form")));
+        assert_eq!(sud("killer1.sud").canonical_hash(), Err(GridError::NotForLayout("canonical form")));
+        assert_eq!(sud("test3.sud").canonical_hash(), Err(GridError::TooLarge { states: 16, max: 9 }));
+    }
+
+    // full - the first full grid the search finds from g
+    fn full(g: &Grid) -> Grid {
+        let mut s = Search::from_grid(g).unwrap();
+        s.run();
+        let mut full = g.clone();
+        for address in 0..g.size {
+            full.give(address, s.first[address] - 1);
+        }
+        full
+    }
+
+    #[test]
+    fn from_sud_names_a_bad_config_token() {
+        let cells = "-".repeat(81);
+        let read = |config: &str| Grid::from_sud(&format!("Title\n{}\n{}\n", config, cells), "bad.sud").err();
+        assert_eq!(read("3x - 123456789"), Some(GridError::BadConfig("3x".to_owned())));
+        assert_eq!(read("cube - 123456789"), Some(GridError::PolydokuShape("cube".to_owned())));
+        assert_eq!(read("0,0;2,2 - 123456789"), Some(GridError::PolydokuShape("0,0;2,2".to_owned())));
+        assert_eq!(read("3x2 - 1234"), Some(GridError::BadBoxes { states: 4, width: 3, height: 2 }));
+        assert_eq!(read("3 - 123456789"), None);
+        assert_eq!(sud("test9.sud").states, 1);
+    }
+
+    #[test]
+    fn claims_and_solve_next_name_their_errors() {
+        let mut g = Grid::from_line("-12-4--31--2-34-", "claims").unwrap();
+        assert_eq!(g.claim_rc(0, 1, 0), Err(GridError::AlreadySolved { row: 0, col: 1 }));
+        assert_eq!(g.claim_a(7, 0), Err(GridError::AlreadySolved { row: 1, col: 3 }));
+        assert_eq!(g.claim_rc(0, 0, 2), Ok(()));
+        assert_eq!(g.claim_a(0, 2), Err(GridError::AlreadySolved { row: 0, col: 0 }));
+
+        let mut g = Grid::from_line("1-1-------------", "repeat").unwrap();
+        assert_eq!(g.solve_next(), Err(GridError::Contradiction { unit: Unit::Row(0), state: '1' }));
+        let mut g = Grid::from_line("1-------1-------", "repeat").unwrap();
+        assert_eq!(g.solve_next(), Err(GridError::Contradiction { unit: Unit::Col(0), state: '1' }));
+        let mut g = Grid::from_line("1----1----------", "repeat").unwrap();
+        assert_eq!(g.solve_next(), Err(GridError::Contradiction { unit: Unit::Block(0), state: '1' }));
+
+        assert_eq!(Grid::with_boxes("1234", 3, 2).err(), Some(GridError::BadBoxes { states: 4, width: 3, height: 2 }));
+        assert!(Grid::with_boxes("1234", 2, 2).is_ok());
+    }
+
+    #[test]
+    fn from_sud_reads_a_numeric_title_as_a_title() {
+        let text = std::fs::read_to_string("sudfiles/test8.sud").unwrap();
+        let title = "1234123412341234";
+        let g = Grid::from_sud(&text.replacen("2025-11-26 Test Duodoku", title, 1), "numeric.sud").unwrap();
+        assert_eq!(g.name, title);
+        assert_eq!(g.to_line(), sud("test8.sud").to_line());
+        let g = Grid::from_sud("-12-4--31--2-34-\n\n", "line.sud").unwrap();
+        assert_eq!(g.states, 4);
+        assert_eq!(g.cells.iter().filter(|c| c.given).count(), 8);
+    }
+
+    // jigsaw_text - jigsaw1.sud with the region layout lines replaced
+    fn jigsaw_text(layout: &[&str]) -> String {
+        let path = format!("{}/sudfiles/jigsaw1.sud", env!("CARGO_MANIFEST_DIR"));
+        let text = std::fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        let mut lines: Vec<&str> = lines[..lines.len() - 9].to_vec();
+        lines.extend(layout);
+        lines.join("\n")
+    }
+
+    #[test]
+    fn jigsaw_regions_are_units() {
+        let mut g = sud("jigsaw1.sud");
+        assert_eq!(g.box_w, 0);
+        assert!(!g.units.kinds.iter().any(|u| matches!(u, Unit::Block(_))));
+        // the D region wanders from r3c3 down to r6c3
+        let d = g.regions[2 * 9 + 2];
+        let region = g.units.kinds.iter().position(|&u| u == Unit::Region(d)).unwrap();
+        assert_eq!(g.units.cells[region], vec![20, 28, 29, 30, 36, 37, 38, 46, 47]);
+        assert!(g.units.of_cell[47].contains(&region));
+        assert_eq!(g.solve(), Ok(1));
+    }
+
+    #[test]
+    fn jigsaw_layouts_are_checked() {
+        let mut layout = ["AAABBBBCC", "AAABBBCCC", "AADBBCCCC", "ADDDEEEFF", "DDDEEEFFF",
+                          "GDDEEEFFF", "GGHHHHFII", "GGGHHIIII", "GGGHHHIII"];
+        assert!(Grid::from_sud(&jigsaw_text(&layout), "jigsaw").is_ok());
+
+        // an A taken over by B leaves 8 and 10
+        layout[0] = "AABBBBBCC";
+        assert_eq!(Grid::from_sud(&jigsaw_text(&layout), "jigsaw").err(),
+                   Some(GridError::RegionSize { region: 0, cells: 8, expected: 9 }));
+        // swapping an A with an I keeps the sizes but splits both
+        layout[0] = "IAABBBBCC";
+        layout[8] = "GGGHHHIIA";
+        assert_eq!(Grid::from_sud(&jigsaw_text(&layout), "jigsaw").err(), Some(GridError::RegionSplit { region: 0 }));
+        // a letter too few
+        layout[8] = "GGGHHHII";
+        assert_eq!(Grid::from_sud(&jigsaw_text(&layout), "jigsaw").err(),
+                   Some(GridError::WrongLength { expected: 81, got: 80 }));
+    }
+
+    #[test]
+    fn jigsaw_regions_move_with_the_cells() {
+        let mut g = sud("jigsaw1.sud");
+        let before = g.clone();
+        assert_eq!(g.permute_rows(0, &[2, 0, 1]), Err(GridError::BadPermutation("row order within a band")));
+        assert_eq!(g.transpose(), Ok(()));
+        for a in 0..81 {
+            let b = a % 9 * 9 + a / 9;
+            assert_eq!(g.cells[a].solved, before.cells[b].solved);
+            // regions are numbered again in reading order, but hold the same cells
+            assert!((0..81).all(|c| (g.regions[a] == g.regions[c]) == (before.regions[b] == before.regions[c % 9 * 9 + c / 9])));
+        }
+        assert_eq!(g.solve(), Ok(1));
+    }
+
+    // with_variants - empty 9x9 grid with the variants named
+    fn with_variants(variants: &[Variant]) -> Grid {
+        let mut g = Grid::new("123456789");
+        for &variant in variants {
+            g.add_variant(variant).unwrap();
+        }
+        g
+    }
+
+    // extra_peers - the peers of address under the variants beyond those of a plain grid
+    fn extra_peers(variants: &[Variant], address: usize) -> Vec<usize> {
+        let plain = Grid::new("123456789");
+        let g = with_variants(variants);
+        assert!(plain.units.peers[address].iter().all(|a| g.units.peers[address].contains(a)));
+        g.units.peers[address].iter().copied().filter(|a| !plain.units.peers[address].contains(a)).collect()
+    }
+
+    #[test]
+    fn diagonal_and_hyper_units() {
+        // r1c9 on the other diagonal gains the six cells of it outside its box
+        assert_eq!(extra_peers(&[Variant::Diagonals], 8), vec![32, 40, 48, 56, 64, 72]);
+        let g = with_variants(&[Variant::Diagonals]);
+        assert!(g.units.of_cell[8].iter().any(|&u| g.units.kinds[u] == Unit::Diagonal(1)));
+        // r2c2 in the first window gains r3c4, r4c3 and r4c4
+        assert_eq!(extra_peers(&[Variant::Hyper], 10), vec![21, 29, 30]);
+        assert_eq!(extra_peers(&[Variant::Hyper], 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn repeat_on_a_diagonal_or_window_is_invalid() {
+        for (variant, (a, b), unit) in [(Variant::Diagonals, (0, 80), Unit::Diagonal(0)), (Variant::Hyper, (10, 30), Unit::Window(0))] {
+            let mut plain = Grid::new("123456789");
+            plain.give(a, 4);
+            plain.give(b, 4);
+            plain.update_status();
+            assert_eq!(plain.status, GridStatus::Incomplete);
+
+            let mut g = with_variants(&[variant]);
+            g.give(a, 4);
+            g.give(b, 4);
+            g.update_status();
+            assert_eq!(g.status, GridStatus::Invalid);
+            assert_eq!(g.conflicts(), vec![Conflict { unit, state: 4, cells: vec![a, b] }]);
+        }
+    }
+
+    #[test]
+    fn chess_move_peers() {
+        let knight = with_variants(&[Variant::AntiKnight]);
+        let king = with_variants(&[Variant::AntiKing]);
+        // from a corner every move stays in its box, so adds nothing
+        assert_eq!(knight.units.moves[0], Vec::<usize>::new());
+        assert_eq!(king.units.moves[0], Vec::<usize>::new());
+        // from the centre all eight knight's moves leave the box, and no king's move does
+        assert_eq!(knight.units.moves[40], vec![21, 23, 29, 33, 47, 51, 57, 59]);
+        assert_eq!(king.units.moves[40], Vec::<usize>::new());
+        // from the corner of the centre box three diagonal steps leave it
+        assert_eq!(king.units.moves[30], vec![20, 22, 38]);
+        assert_eq!(extra_peers(&[Variant::AntiKnight], 40), knight.units.moves[40]);
+    }
+
+    #[test]
+    fn non_consecutive_neighbours() {
+        let mut g = with_variants(&[Variant::NonConsecutive]);
+        assert_eq!(g.units.neighbours[0], vec![1, 9]);
+        assert_eq!(g.units.neighbours[40], vec![31, 39, 41, 49]);
+        g.give(40, 4);
+        g.update_possible();
+        let cands = g.candidates();
+        for &a in &g.units.neighbours[40] {
+            assert_eq!(cands[a], 0x1ff & !(0b111 << 3), "{}", a); // no 4, 5 or 6
+        }
+        assert_eq!(cands[30], 0x1ff & !(1 << 4)); // diagonal: only the 5 of its box
+        g.give(41, 5);
+        assert_eq!(g.conflicts(), vec![Conflict { unit: Unit::Neighbours(40), state: 4, cells: vec![40, 41] }]);
+    }
+
+    #[test]
+    fn latin_square_has_no_boxes() {
+        // 7 states have no box shape, so only a Latin square will do
+        assert_eq!(Grid::new("1234567").status, GridStatus::NotSquare);
+        let mut g = Grid::latin("1234567");
+        assert!(g.is_latin());
+        assert!(g.units.kinds.iter().all(|u| matches!(u, Unit::Row(_) | Unit::Col(_))));
+        assert_eq!(g.units.peers[0].len(), 12);
+
+        // a cyclic square with its diagonal cleared is unique - each gap is the last in its row
+        for a in (0..49).filter(|a| a % 8 != 0) {
+            g.give(a, ((a / 7 + a % 7) % 7) as Snumb);
+        }
+        assert_eq!(g.solve(), Ok(1));
+        assert!((0..7).all(|r| g.cells[r * 8].solution == (2 * r % 7) as Snumb));
+        // r1c2 and r2c1 would share a box in a boxed grid, but may repeat here
+        assert_eq!(g.cells[1].solution, g.cells[7].solution);
+        assert_eq!(Grid::from_sud("Latin\nlatin - 12\n1-\n-1\n", "latin").map(|g| g.count_solutions(2)), Ok(1));
+    }
+
+    #[test]
+    fn futoshiki_signs_prune_and_break() {
+        let mut g = sud("futoshiki1.sud");
+        assert!(g.is_latin());
+        g.update_possible();
+        // r1c1 is greater than r1c2 and r2c1, so not 1, and r1c2 less than r1c3, so not 5
+        let cands = g.candidates();
+        assert_eq!(cands[0] & 1, 0);
+        assert_eq!(cands[1] & 1 << 4, 0);
+        assert!(g.rate().unwrap().solved);
+
+        g.give(0, 1);
+        g.give(1, 3);
+        let violations = g.violations();
+        assert_eq!(violations.len(), 1);
+        assert_eq!((violations[0].constraint, violations[0].cells.clone()), (0, vec![0, 1]));
+        assert_eq!(violations[0].reason, "isn't met, the first being greater");
+        g.update_status();
+        assert_eq!(g.status, GridStatus::Invalid);
+    }
+
+    #[test]
+    fn conflicts_name_every_clashing_cell() {
+        // rows 4 and 5 repeat one state throughout, and row 6 three states three times
+        let mut g = sud("error2.sud");
+        let conflicts = g.validate();
+        assert_eq!(conflicts[0], Conflict { unit: Unit::Row(3), state: 0, cells: (27..36).collect() });
+        assert_eq!(conflicts[2], Conflict { unit: Unit::Row(5), state: 2, cells: vec![45, 46, 47] });
+        let units: Vec<Unit> = conflicts.iter().map(|c| c.unit).collect();
+        assert_eq!(units, [Unit::Row(3), Unit::Row(4), Unit::Row(5), Unit::Row(5), Unit::Row(5),
+                           Unit::Block(3), Unit::Block(3), Unit::Block(3), Unit::Block(4), Unit::Block(4),
+                           Unit::Block(4), Unit::Block(5), Unit::Block(5), Unit::Block(5)]);
+        assert!((27..54).all(|a| g.cells[a].highlight == 2));
+        g.update_status();
+        assert_eq!(g.status, GridStatus::Invalid);
+
+        // a state given twice in the first column, the first occurrence listed first
+        let first = |name: &str| sud(name).conflicts().into_iter().map(|c| (c.unit, c.state, c.cells)).collect::<Vec<_>>();
+        assert_eq!(first("test6.sud"), vec![(Unit::Col(0), 7, vec![0, 42 * 49])]);
+        assert_eq!(first("test7.sud"), vec![(Unit::Col(0), 7, vec![0, 48 * 64]), (Unit::Col(0), 8, vec![9 * 64, 56 * 64])]);
+
+        // error1's error is its several solutions, named in its title, not a clash
+        let mut g = sud("error1.sud");
+        assert!(g.name.contains("multiple solutions"));
+        assert!(g.validate().is_empty());
+        assert_eq!(g.count_solutions(2), 2);
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        let s = Json::str("a\"b\\c\nd\re\tf\u{1}\u{1f}");
+        assert_eq!(s.to_string(), r#""a\"b\\c\nd\re\tf\u0001\u001f""#);
+        assert_eq!(Json::str("αβγ λπ").to_string(), "\"αβγ λπ\"");
+        let doc = Json::Obj(vec![
+            ("a", Json::Arr(vec![Json::num(1), Json::Null, Json::Bool(true)])),
+            ("b", Json::Num(f64::NAN)),
+            ("c", Json::Obj(vec![])),
+        ]);
+        assert_eq!(doc.to_string(), r#"{"a":[1,null,true],"b":null,"c":{}}"#);
+    }
+
+    #[test]
+    fn json_keeps_greek_symbols() {
+        let text = std::fs::read_to_string("sudfiles/test1f.sud").unwrap();
+        let g = Grid::from_sud(&text, "test1f").unwrap();
+        let json = g.to_json().to_string();
+        assert!(json.starts_with(r#"{"name":"2025-04-22 Pρόκληση (próklisi = a challenge)","state_dict":"αβγδεθλμπ","#));
+        assert!(json.contains(r#"{"row":1,"col":1,"given":true,"solved":true,"value":"λ","candidates":[]}"#));
+    }
+
+    #[test]
+    fn tab_shows_pencil_marks() {
+        colored::control::set_override(false);
+        let mut g = Grid::from_line("-1------3----2--", "small").unwrap();
+        assert_eq!(g.tab_text(None), concat!(
+            "small 3/16\n",
+            ".2    | .2 .2\n",
+            ".4  1 | 34 34\n",
+            "      |\n",
+            ".2 .. | 12 12\n",
+            ".4 34 | 34 34\n",
+            "------+------\n",
+            "   .. | 12 12\n",
+            " 3 .4 | .4 .4\n",
+            "      |\n",
+            "1.    | 1. 1.\n",
+            ".4  2 | 34 34\n",
+        ));
+    }
+
+    #[test]
+    fn tab_keeps_16x16_columns_aligned() {
+        colored::control::set_override(false);
+        let mut g = Grid::new("0123456789ABCDEF");
+        g.claim_rc(0, 0, 15).unwrap();
+        g.claim_rc(5, 9, 3).unwrap();
+        g.claim_rc(15, 15, 0).unwrap();
+        let text = g.tab_text(None);
+        let lines: Vec<&str> = text.lines().skip(1).collect();
+        assert_eq!(lines.len(), 16 * 4 + 15);
+        let bars = |line: &str| line.char_indices().filter(|&(_, c)| c == '|' || c == '+').map(|(i, _)| i).collect::<Vec<_>>();
+        for line in &lines {
+            assert_eq!(bars(line), [20, 42, 64], "{:?}", line);
+            assert!(line.len() <= 85);
+        }
+        // a full cell's marks, a solved cell's value in the middle, and a box rule
+        assert!(lines[0].starts_with("     0123 0123"));
+        assert!(lines[2].starts_with("  F  89AB"));
+        assert!(lines[4 * 5 - 1].starts_with("-".repeat(20).as_str()));
+    }
+
+    #[test]
+    fn solve_fills_a_unique_puzzle() {
+        let mut g = sud("test2a.sud");
+        assert_eq!(g.count_solutions(2), 1);
+        assert_eq!(g.solve(), Ok(1));
+        assert!(g.cells.iter().all(|c| c.solved));
+        g.update_status();
+        assert_eq!(g.status, GridStatus::Solved);
+    }
+
+    #[test]
+    fn solve_leaves_several_solutions_alone() {
+        for name in ["test1a.sud", "test1b.sud", "test1c.sud", "test1d.sud", "test1e.sud", "test1f.sud"] {
+            let mut g = sud(name);
+            let before = g.to_line();
+            assert_eq!(g.count_solutions(2), 2, "{}", name);
+            assert_eq!(g.solve(), Ok(2), "{}", name);
+            assert_eq!(g.to_line(), before, "{}", name);
+        }
+    }
+
+    #[test]
+    fn solve_finds_nothing_for_an_unsolvable_puzzle() {
+        // 1-8 along the top row leave only 9 for its last cell, which the 9 below rules out
+        let mut g = Grid::new("123456789");
+        for col in 0..8 {
+            g.give(col, col as Snumb);
+        }
+        g.give(9 + 6, 8);
+        let before = g.to_line();
+        assert_eq!(g.count_solutions(2), 0);
+        assert_eq!(g.solve(), Ok(0));
+        assert_eq!(g.to_line(), before);
+    }
+
+    #[test]
+    fn solve_handles_other_sizes() {
+        let mut g = sud("test8.sud");
+        assert_eq!(g.solve(), Ok(1));
+        assert!(g.cells.iter().all(|c| c.solved));
+
+        // a full 16x16 grid with its diagonal cleared is unique - each gap is the last in its row
+        let answer = full(&Grid::sized(16).unwrap());
+        let mut g = answer.clone();
+        for address in (0..g.size).step_by(17) {
+            g.cells[address].solved = false;
+            g.cells[address].given = false;
+        }
+        assert_eq!(g.solve(), Ok(1));
+        assert_eq!(g.to_line(), answer.to_line());
+    }
+
+    #[test]
+    fn solve_calcudoku() {
+        let mut g = sud("calcudoku1.sud");
+        assert!(g.rate().unwrap().solved);
+        assert_eq!(g.solve(), Ok(1));
+        let values: Vec<u8> = g.cells.iter().map(|c| c.solution + 1).collect();
+        assert!(g.constraints.iter().all(|c| c.broken(&values, 6).is_none()));
+        assert!(g.validate().is_empty());
+    }
+
+    #[test]
+    fn minimise_leaves_a_minimal_unique_puzzle() {
+        let mut g = sud("test2a.sud");
+        let mut answer = g.clone();
+        assert_eq!(answer.solve(), Ok(1));
+        let givens = |g: &Grid| -> Vec<usize> { (0..g.size).filter(|&a| g.cells[a].solved).collect() };
+        let before = givens(&g);
+
+        let redundant = g.minimise().unwrap();
+        let after = givens(&g);
+        let removed: Vec<usize> = before.iter().copied().filter(|a| !after.contains(a)).collect();
+        assert!(!redundant.is_empty());
+        assert_eq!(redundant, removed);
+
+        // still the same single solution, and no given left can go
+        let mut solved = g.clone();
+        assert_eq!(solved.solve(), Ok(1));
+        assert_eq!(solved.to_line(), answer.to_line());
+        for &address in &after {
+            let mut h = g.clone();
+            h.cells[address].solved = false;
+            h.cells[address].given = false;
+            assert_eq!(h.count_solutions(2), 2, "r{}c{}", address / 9 + 1, address % 9 + 1);
+        }
+        assert_eq!(sud("test1a.sud").minimise(), Err(GridError::NotUnique { solutions: 2 }));
+    }
+
+    #[test]
+    fn count_solutions_stops_at_its_limit() {
+        let g = Grid::new("123456789");
+        for limit in [1, 2, 5, 40] {
+            assert_eq!(g.count_solutions(limit), limit);
+        }
+        assert_eq!(sud("test1a.sud").count_solutions(1), 1);
+        assert_eq!(Grid::sized(16).unwrap().count_solutions(3), 3);
+    }
+}
@@ -1,712 +1,843 @@
-// Sudoku solver
-// with Hexdoku capability
-// Jon Knox, 2025-11
-
-// Goals
-// 1. Define structures/implementations successfully, print name of cell
-// 2. Be able to print the grid by fmt:Display and fmt:Debug
-// 3. Apply functions to grid for cell status updates...
-//    a) tritri application of not-allowed
-//    b) column application of not-allowed
-//    c) row application of not-allowed
-// 5. Have CLI loop which has 'read', 'tritri', 'column', 'row' and 'show' functions
-// 6. IO stuff
-//    a) settle on file format, write test files
-//    b) implement a 'write' function
-//    c) implement a 'read' function
-// 7. Perform 'next' = one-step fill in a next "obvious" solution and display with terminal highlight
-// 8. Detect dependent pair in tri-tri
-// 9. Implement cell status updates in ARM assembly language
-// 10. Implement heuristic function for solving
-//    a) single fn thread from next free cell
-//    b) implement multiple threads (on M4 cpu)
-//    c) select best-case starting point
-// 11. Change dimensions from 9x9 of 3x3 3x3s of 1..9 to 16x16 of 4x4 of 4x4 of 0..9,a..f
-
-// Notes
-// vector alternatives described here:
-// Re: https://play.rust-lang.org/?version=nightly&mode=debug&edition=2018&gist=174c2ddb88ce053af6206927890d3591
-//
-
-use core::panic;
-// Imports
-use std::{arch::aarch64, fmt};
-//use std::error::Error;
-//use colored_text::Colorize;
-// Re: https://github.com/seapagan/colored_text/blob/main/examples/basic.rs
+// Sudoku solver - command line front end
+// the solver itself is the sudoku library (src/lib.rs), this only handles the
+// arguments, the interactive loop and output for scripts
 
 use colored::Colorize;
-
-// sudoku size (for 'classic', this is 9 states/cell, grid of 9 wide, 9 high)
-const MAXSTATES: usize = 9; // max number of diff states a cell can have
-const MAXROOTS: usize = 3; // max number of block size (isqrt of MAXSTATES)
-const WIDTH: u8 = 9;
-const HEIGHT: u8 = 9;
-
-// sudoku number
-type Snumb = u8; // holds values 1..9 or 0 for unknown
-
-/// cell is a single element that holds a solution number (snumb)
-// uses value 0 if unsolved
-// the disallowed vector is an array [1..9] of known disallowed values
-#[derive(Clone)]
-struct Cell {
-    solved: bool,    // whether the cell is solved
-    solution: Snumb, // solved value of cell (only when self.solved==true)
-    possible: Vec<bool>,
-    disallowed: Vec<bool>,
-    ispaired: bool,   //unused
-    paired: (u8, u8), // unused
-    highlight: u8,
-}
-
-/// Status of a Grid
-#[derive(PartialEq)]
-enum GridStatus {
-    Solved,     // All cells are complete and logically correct
-    Incomplete, // Puzzle is incomplete, number of cells remaining unsolved (include empty)
-    Invalid,    // There are logic errors
-    Unsolvable, // Nonspecific - has multiple solutions
-    Empty,      // Empty grid, ready to load
-    NotSquare,  // States count not a square number
-}
-
-/// grid consists of 9x9 cells
-struct Grid {
-    name: String,       // e.g. "Dummy Sudoku"
-    state_dict: String, // e.g. "123456789"
-    status: GridStatus,
-    states: u8, // states is also width is also height
-    isqrt: u8,  // integer sq root of states count
-    size: u8,   // number of cells = states^2
-    cells: Vec<Cell>,
-    symbols: Vec<char>,
-}
-
-// Implement display trait
-impl fmt::Display for Grid {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        // calculate solved cell count
-        let mut used: u32 = 0;
-        let total: usize = self.cells.len();
-        for i in 0..total {
-            if self.cells[i].solved {
-                used += 1;
-            }
+use sudoku::kernel::{KERNELS, Kernel};
+use sudoku::poly::Polydoku;
+use sudoku::{Conflict, Grid, GridError, GridStatus, Json, Rating, Rng, Snumb, Step, TECHNIQUES, Technique, Variant,
+             default_states};
+
+// repl - interactive command loop, with line editing and history kept in a local file
+fn repl(filename: Option<String>) {
+    const HISTORY: &str = ".sudoku_history";
+
+    let mut rl = match rustyline::DefaultEditor::new() {
+        Ok(rl) => rl,
+        Err(e) => {
+            println!("Unable to start line editor: {}", e);
+            return;
         }
-        write!(f, "{}: {}/{}", self.name, used, total)
-    }
-}
-
-// Implement associated functions
-
-impl Cell {
-    fn empty(states: u8) -> Cell {
-        Cell {
-            solved: false,                            // whether the cell is solved
-            solution: 0,                              // don't care
-            possible: vec![false; states as usize],   // not computed
-            disallowed: vec![false; states as usize], // not computed
-            ispaired: false,
-            paired: (0, 0),
-            highlight: 0,
-        }
-    }
-}
+    };
+    let _ = rl.load_history(HISTORY);
 
-impl Grid {
-    /// new - create an empty grid
-    fn new(states: &str) -> Grid {
-        let nstates = states.len() as u8;
-        let int_sq_root = (nstates as f64).sqrt() as u32;
-
-        let mut s = GridStatus::Empty;
-        if nstates as u32 != int_sq_root * int_sq_root {
-            s = GridStatus::NotSquare;
-        }
-
-        Grid {
-            name: format!("Empty grid for {}", states),
-            state_dict: states.to_owned(),
-            status: s,
-            states: nstates,
-            isqrt: int_sq_root as u8,
-            size: nstates * nstates,
-            cells: vec![Cell::empty(nstates); (nstates * nstates) as usize],
-            symbols: vec!['1', '2', '3', '4', '5', '6', '7', '8', '9'],
+    let mut g = Grid::new("123456789");
+    if let Some(f) = filename {
+        println!("Loading file {}", f);
+        match g.load(f) {
+            Ok(()) => g.print(true),
+            Err(e) => println!("Error: {}", e),
         }
     }
-}
-
-
-
-
-// Implement grid methods
-impl Grid {
-    // isempty - is an initialised empty grid
-    fn isempty(&self) -> bool {
-        self.status == GridStatus::Empty
-    }
-
-    fn bodge(&mut self, title: String, arr: Vec<u8>) -> Result<u32, &'static str> {
-        self.name = title;
 
-        if self.size != arr.len() as u8 {
-            return Err("wrong length vector provided");
-        }
-
-        // populate grid with empty cells, unless solved (in 1...9)
-        let mut used: u32 = 0;
-        for i in 0..arr.len() {
-            if arr[i] > 0 {
-                self.cells[i].solved = true;
-                self.cells[i].solution = arr[i] - 1;
-                used += 1;
-            }
+    // snapshots of the whole grid (cells, constraints, variants and status) for undo/redo
+    let mut undo: Vec<Grid> = Vec::new();
+    let mut redo: Vec<Grid> = Vec::new();
+    // the step last applied, whose eliminations 'show pencil' marks
+    let mut last: Option<Step> = None;
+
+    loop {
+        let line = match rl.readline("sudoku> ") {
+            Ok(line) => line,
+            Err(rustyline::error::ReadlineError::Interrupted) => continue,
+            Err(_) => break, // end of input
+        };
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.is_empty() {
+            continue;
         }
-
-        Ok(used)
-    }
-
-    // load - get grid from file
-    fn load(&self, filename: String) -> bool {
-        println!("Loading file");
-        true
-    }
-
-    // save - save current grid to file
-    fn save(&self, filename: String) -> bool {
-        println!("Saving file");
-        true
-    }
-
-    // validate - check logic of current grid
-    fn validate(&mut self) -> bool {
-        println!("{}","Validating grid".underline());
-
-        let mut valid = true;
-        let mut ticked = [false; MAXSTATES];
-        let mut start;
-
-        // check horizontals
-        for y in 0..self.states { // per row
-            for el in 0..self.states {
-                ticked[el as usize] = false;
-            }
-
-            start = y * self.states;
-            for x in 0..self.states {
-               //print!(" {}", x);
-                let address = (start + x) as usize;
-                if self.cells[address].solved {
-                    let sol = self.cells[address].solution as usize;
-                    if ticked[sol] {
-                        // this solution already used on this line
-                        self.cells[address].highlight = 2;
-                        println!("Bad cell - horizontally repeated '{}' in ({},{})", self.symbols[sol], x+1,y+1);
-                        valid = false;
+        let _ = rl.add_history_entry(line.as_str());
+
+        let before = g.clone();
+        let mut changed = false; // grid modified, so can be undone
+        let mut stepped = None;  // the step making the change, if it was one
+
+        match words[0] {
+            "help" | "?" => repl_help(),
+            "quit" | "exit" => break,
+            "load" | "read" if words.len() == 2 => {
+                println!("Loading file {}", words[1]);
+                match g.load(words[1].to_owned()) {
+                    Ok(()) => {
+                        undo.clear();
+                        redo.clear();
+                        g.print(true);
                     }
-                    ticked[sol] = true;
+                    Err(e) => println!("Error: {}", e),
                 }
             }
-        }
-
-        // check verticals
-        for x in 0..self.states { // per col
-            for el in 0..self.states {
-                ticked[el as usize] = false;
+            "save" | "write" if words.len() == 2 => {
+                println!("Saving file {}", words[1]);
+                if let Err(e) = g.save(words[1].to_owned()) {
+                    println!("Error: {}", e);
+                }
             }
-            
-            for y in 0..self.states {
-                let address = (x + y*self.states) as usize;
-                if self.cells[address].solved {
-                    let sol = self.cells[address].solution as usize;
-                    if ticked[sol] {
-                        // this solution already used on this line
-                        self.cells[address].highlight = 2;
-                        println!("Bad cell - vertically repeated '{}' in ({},{})", self.symbols[sol], x+1,y+1);
-                        valid = false;
-                    }
-                    ticked[sol] = true;
+            "show" => {
+                if words.get(1) == Some(&"candidates") {
+                    g.print_candidates();
+                } else if words.get(1) == Some(&"pencil") {
+                    g.tab(last.as_ref());
+                } else {
+                    g.print(true);
                 }
             }
-        }
-
-        // check blocks
-        //println!("i={} s= {}", self.isqrt, self.states);
-        for b in 0..self.states { // per block
-            for el in 0..self.states {
-                ticked[el as usize] = false;
+            "validate" => {
+                if report_conflicts(&mut g) {
+                    println!("Grid is valid");
+                } else {
+                    g.print(false);
+                }
             }
-
-            let bx = (b % self.isqrt) * self.isqrt;
-            let by = (b / self.isqrt) * self.isqrt * self.states;
-            // println!("{} for {}+{}", b, bx, by);
-
-            for y in 0..self.isqrt {
-                for x in 0..self.isqrt {
-                    let address = (bx+by+x+y*self.states) as usize;
-                    //println!("a={} ", address);
-                    if self.cells[address].solved {
-                        let sol = self.cells[address].solution as usize;
-                        if ticked[sol] {
-                            // this solution already used in this block
-                            self.cells[address].highlight = 2;
-                            println!("Bad block - repeated '{}' in block {}", self.symbols[sol], b+1);
-                            valid = false;
+            "next" => match g.next_step() {
+                Some(step) => {
+                    println!("{}: {}", step.technique.name(), step.reason);
+                    match g.apply_step(&step) {
+                        Ok(()) => (changed, stepped) = (true, Some(step)),
+                        Err(e) => println!("Error: {}", e),
+                    }
+                }
+                None => println!("No step found"),
+            },
+            "apply" if words.len() == 2 => match Technique::from_name(words[1]) {
+                Some(t) => match g.find_step(t) {
+                    Some(step) => {
+                        println!("{}: {}", step.technique.name(), step.reason);
+                        match g.apply_step(&step) {
+                            Ok(()) => (changed, stepped) = (true, Some(step)),
+                            Err(e) => println!("Error: {}", e),
                         }
-                        ticked[sol] = true;
                     }
+                    None => println!("No {} found", t.name()),
+                },
+                None => println!("Unknown technique '{}' - try 'techniques'", words[1]),
+            },
+            "techniques" => {
+                for t in TECHNIQUES {
+                    println!("{:<18} weight {}", t.name(), t.weight());
                 }
             }
+            "set" if words.len() == 4 => match repl_cell(&g, words[1], words[2]) {
+                Some(address) if g.cells[address].solved => {
+                    println!("{} is already solved - clear it first", g.cell_name(address));
+                }
+                Some(address) => {
+                    let ch = words[3].chars().next().unwrap();
+                    match g.symbols.iter().position(|&s| s == ch) {
+                        Some(state) if words[3].chars().count() == 1 => match g.claim_a(address, state as Snumb) {
+                            Ok(()) => changed = true,
+                            Err(e) => println!("Error: {}", e),
+                        },
+                        _ => println!("'{}' is not one of {}", words[3], g.state_dict),
+                    }
+                }
+                None => println!("No cell at row {} column {}", words[1], words[2]),
+            },
+            "clear" if words.len() == 3 => match repl_cell(&g, words[1], words[2]) {
+                Some(address) => {
+                    let n = g.states as usize;
+                    g.clear_rc(address / n, address % n);
+                    changed = true;
+                }
+                None => println!("No cell at row {} column {}", words[1], words[2]),
+            },
+            "undo" => match undo.pop() {
+                Some(grid) => {
+                    redo.push(std::mem::replace(&mut g, grid));
+                    g.print(true);
+                }
+                None => println!("Nothing to undo"),
+            },
+            "redo" => match redo.pop() {
+                Some(grid) => {
+                    undo.push(std::mem::replace(&mut g, grid));
+                    g.print(true);
+                }
+                None => println!("Nothing to redo"),
+            },
+            "hint" => match g.next_step() {
+                Some(step) => match step.placed.first().or(step.eliminated.first()) {
+                    Some(&(address, _)) => {
+                        println!("Hint: look for a {} around {}", step.technique.name(), g.cell_name(address));
+                    }
+                    None => println!("Hint: look for a {}", step.technique.name()),
+                },
+                None => println!("No logical step found - try 'solve'"),
+            },
+            "solve" => match g.solve() {
+                Ok(0) => println!("No solution"),
+                Ok(1) => changed = true,
+                Ok(_) => println!("More than one solution - not filled in"),
+                Err(e) => println!("Error: {}", e),
+            },
+            "rate" => match g.rate() {
+                Ok(r) => println!("{}", r),
+                Err(e) => println!("Error: {}", e),
+            },
+            _ => println!("Unknown command '{}' - try 'help'", line.trim()),
         }
 
-        valid
+        if changed || matches!(words[0], "load" | "read" | "undo" | "redo") {
+            last = stepped;
+        }
+        if changed {
+            undo.push(before);
+            redo.clear();
+            g.print(true);
+        }
     }
 
+    let _ = rl.save_history(HISTORY);
+}
 
-//  I think I want to be doing this:
-//     fn example(width: usize, height: usize) {
-//         // Base 1d array
-//         let mut grid_raw = vec![0; width * height];
-//
-//         // Vector of 'width' elements slices
-//         let mut grid_base: Vec<_> = grid_raw.as_mut_slice().chunks_mut(width).collect();
-//
-//         // Final 2d array `&mut [&mut [_]]`
-//         let grid = grid_base.as_mut_slice();
-//
-//         // Accessing data
-//         grid[0][0] = 4;
-//     }
-// Re: https://stackoverflow.com/questions/13212212/creating-two-dimensional-arrays-in-rust
-
+// repl_cell - address of the cell at 1-based row and column given as text
+fn repl_cell(g: &Grid, row: &str, col: &str) -> Option<usize> {
+    let n = g.states as usize;
+    let row: usize = row.parse().ok()?;
+    let col: usize = col.parse().ok()?;
+    if (1..=n).contains(&row) && (1..=n).contains(&col) {
+        Some((row - 1) * n + col - 1)
+    } else {
+        None
+    }
+}
 
+fn repl_help() {
+    println!("load FILE        read a .sud file (also 'read')");
+    println!("save FILE        write the grid to a .sud file (also 'write')");
+    println!("show             show the grid");
+    println!("show candidates  show the grid with candidates of unsolved cells");
+    println!("show pencil      show each unsolved cell as a mini-grid of its candidates,");
+    println!("                 with the last step's eliminations in red");
+    println!("validate         check for repeated states and broken constraints");
+    println!("next             apply the easiest logical step");
+    println!("apply TECHNIQUE  apply one step of a named technique");
+    println!("techniques       list the techniques");
+    println!("set R C V        set the cell at row R, column C to V");
+    println!("clear R C        clear the cell at row R, column C");
+    println!("undo, redo       step back or forward through changes");
+    println!("hint             say which technique to look for, and where");
+    println!("solve            fill in the solution by brute force");
+    println!("rate             grade the difficulty of the grid");
+    println!("quit             leave (also 'exit')");
+}
 
-    // claim(r,c,state) - set a blank to a solution at (row,col)
-    fn claim_rc(&mut self, row: usize, col: usize, sol: Snumb) {
-        let mut address = (row * self.states as usize + col);
-        self.claim_a(address, sol);
+// exit code for bad arguments or unreadable files - grid outcomes use exit_code()
+const EXIT_ERROR: i32 = 1;
+
+// exit_code - process exit code for a grid status, so scripts can tell the outcome
+fn exit_code(status: &GridStatus) -> i32 {
+    match status {
+        GridStatus::Solved => 0,
+        GridStatus::Invalid => 2,
+        GridStatus::Unsolvable => 3,
+        GridStatus::Incomplete => 4,
+        GridStatus::NotSquare => 5,
+        GridStatus::Empty => 6,
     }
+}
+
+fn usage() -> i32 {
+    println!("usage: sudoku COMMAND [ARGS]");
+    println!("  solve FILE                    fill in the solution");
+    println!("  validate FILE                 check for repeated states and broken constraints");
+    println!("  rate FILE                     grade the difficulty by logical solving");
+    println!("  generate --size N [--seed S]  make a new puzzle of N states (4, 6, 8, 9, 12, 16, ...)");
+    println!("           [--boxes WxH]        with boxes W columns wide and H rows high");
+    println!("           [--variant NAME]     with extra rules: diagonals, hyper, antiknight, antiking,");
+    println!("                                nonconsecutive, toroidal (repeatable)");
+    println!("           [--shape NAME]       a polydoku: samurai, twodoku, butterfly, flower, sohei,");
+    println!("                                or cube");
+    println!("  convert IN OUT                rewrite as .sud, or one line for other names");
+    println!("  canon FILE                    show canonical form and hash");
+    println!("  minimise FILE                 remove redundant givens");
+    println!("  batch PATH [--no-time]        solve and rate every puzzle in a directory of");
+    println!("                                .sud files or a file of one-line puzzles");
+    println!("                                (exits with the highest code of any puzzle)");
+    println!("  poly FILE                     solve a polydoku (overlapping grids, e.g. samurai, or a cube)");
+    println!("  repl [FILE]                   interactive command loop");
+    println!("  demo                          run the original demo");
+    println!("  kernels                       list the unit-scan kernels for this machine");
+    println!("  --json                        (with any command but repl/demo) print one JSON");
+    println!("                                document instead of text");
+    println!("  --kernel NAME                 unit-scan kernel to use (default the best available)");
+    println!("exit codes: 0 solved/done, 1 bad arguments or file, 2 invalid, 3 unsolvable,");
+    println!("            4 incomplete, 5 states can't be divided into boxes, 6 empty grid");
+    println!("            (3 is no solution or more than one, which solve and rate tell apart");
+    println!("            in what they print; rate gives 4 for a unique puzzle that needs search)");
+    EXIT_ERROR
+}
 
-    // claim_a(a,state) - set a blank to a solution at addr=a
-    fn claim_a(&mut self, address: usize, sol: Snumb) {
-        if self.cells[address].solved {
-            println!("\nClaim a={} as {} failed",address, sol);
-            panic!();
+// cli_load - load the grid for a command, exiting if that fails
+// in json mode the file is read quietly and a failure is reported as {"error": ...}
+fn cli_load(filename: &str, json: bool) -> Grid {
+    if !json {
+        println!("Loading file {}", filename);
+    }
+    let mut g = Grid::new("123456789");
+    if let Err(e) = g.load(filename.to_owned()) {
+        if json {
+            json_error(&e);
+        } else {
+            println!("Error: {}", e);
+            if let GridError::PolydokuShape(_) = e {
+                println!("Use `sudoku poly {}` for a polydoku", filename);
+            }
         }
-        self.cells[address].solved = true;
-        self.cells[address].solution = sol;
-        self.cells[address].highlight = 1;
+        std::process::exit(error_code(&e));
     }
+    g.update_status();
+    g
+}
 
-    
-    // validate - check logic of current grid
-    fn solve_next(&mut self) -> u8 {
+// error_code - process exit code for an error, matching the grid status it implies
+fn error_code(e: &GridError) -> i32 {
+    match e {
+        GridError::NotSquare { .. } => exit_code(&GridStatus::NotSquare),
+        GridError::Contradiction { .. } | GridError::Unsatisfied(_) => exit_code(&GridStatus::Invalid),
+        GridError::NotUnique { .. } => exit_code(&GridStatus::Unsolvable),
+        _ => EXIT_ERROR,
+    }
+}
 
-        // do cross-check to see:  possible to put `state` in cell `element`?
-        // this takes into account solved cells (blocked)
-        // an attempt to cross-check a cell containing the target is a panic
-        // 
-        fn possible(g: &Grid, element: usize, state: Snumb) -> bool {
+// json_error - print an error as a JSON document, returning its exit code
+fn json_error(e: &GridError) -> i32 {
+    println!("{}", Json::Obj(vec![("error", Json::str(e.to_string()))]));
+    error_code(e)
+}
 
-            // simplify the expressions which use width
-            let width = g.states as usize;
+// report_conflicts - validate the grid, printing any conflicts and broken constraints,
+// and say whether it is valid
+fn report_conflicts(g: &mut Grid) -> bool {
+    println!("{}","Validating grid".underline());
+    let conflicts = g.validate();
+    for conflict in &conflicts {
+        println!("{}", g.conflict_text(conflict));
+    }
+    let violations = g.violations();
+    for violation in &violations {
+        println!("{}", g.violation_text(violation));
+    }
+    conflicts.is_empty() && violations.is_empty()
+}
 
-            // translate element address into (r,c) 
-            let r = element / width;
-            let c = element - r * width;
-            println!("Translated {} into {},{}",element,r,c);
+// conflicts_json - conflicts as a JSON array
+fn conflicts_json(g: &Grid, conflicts: &[Conflict]) -> Json {
+    Json::Arr(conflicts.iter().map(|c| g.conflict_json(c)).collect())
+}
 
+// violations_json - broken constraints as a JSON array
+fn violations_json(g: &Grid) -> Json {
+    Json::Arr(g.violations().iter().map(|v| g.violation_json(v)).collect())
+}
 
-            // check the horiz row, returning early with false if can
-            for col in 0..width as usize {
-                // don't check self
-                // if c==col {
-                //     continue;
-                // }
-                // checking the ...
-                let address = r * width + col;
-                println!("R Addr={}",address);
-                if !g.cells[address].solved {
-                    continue;
-                }
-                if g.cells[address].solution==state {
-                    println!("Not possible due to row {} = {},{}",address,r,col);
-                    return false;
-                }
+fn cmd_solve(filename: &str, json: bool) -> i32 {
+    let mut g = cli_load(filename, json);
+    let mut solutions = 0;
+    let mut conflicts = Vec::new();
+    if g.status == GridStatus::Invalid {
+        conflicts = g.validate();
+    } else {
+        match g.solve() {
+            Ok(1) => {
+                solutions = 1;
+                g.update_status();
             }
-
-            // check the vert column, returning early with false if can
-            for row in 0..width as usize {
-                // don't check self
-                // if r==row {
-                //     continue;
-                // }
-                // checking the ...
-                let address = row * width + c;
-                println!("C Addr={}",address);
-
-                if !g.cells[address].solved {
-                    continue;
-                }
-                if g.cells[address].solution==state {
-                    println!("Not possible due to column {} = {},{}",address,row,c);
-                    return false;
-                }
+            Ok(count) => {
+                solutions = count;
+                g.status = GridStatus::Unsolvable;
+            }
+            Err(e) if json => return json_error(&e),
+            Err(e) => {
+                println!("Error: {}", e);
+                return error_code(&e);
             }
-
-            // has to be valid - return true
-            true
         }
+    }
 
-        // // TEST middle cell can take state 4 (is centre free and can do 5?)
-        // let dummy = possible(self, 39, 3);
-        // println!("{}", dummy);
-        // return 0;
-
-        // ----------------
-
-
-
-        println!("{}","Running solve_next".underline());
+    if json {
+        println!("{}", Json::Obj(vec![
+            ("status", Json::str(format!("{:?}", g.status))),
+            ("solutions", Json::num(solutions)),
+            ("conflicts", conflicts_json(&g, &conflicts)),
+            ("violations", violations_json(&g)),
+            ("grid", g.to_json()),
+        ]));
+        return exit_code(&g.status);
+    }
+    if g.status == GridStatus::Invalid {
+        println!("{}","Validating grid".underline());
+        for conflict in &conflicts {
+            println!("{}", g.conflict_text(conflict));
+        }
+        for violation in &g.violations() {
+            println!("{}", g.violation_text(violation));
+        }
+    } else if solutions == 0 {
+        println!("No solution");
+    } else if solutions > 1 {
+        println!("More than one solution");
+    }
+    g.print(true);
+    exit_code(&g.status)
+}
 
-        // return value is number of cells added
-        // (this is used to re-call the fn until exhaustion)
-        let mut added = 0u8;
+fn cmd_validate(filename: &str, json: bool) -> i32 {
+    let mut g = cli_load(filename, json);
+    if json {
+        let conflicts = g.validate();
+        let violations = g.violations();
+        println!("{}", Json::Obj(vec![
+            ("status", Json::str(format!("{:?}", g.status))),
+            ("valid", Json::Bool(conflicts.is_empty() && violations.is_empty())),
+            ("conflicts", conflicts_json(&g, &conflicts)),
+            ("violations", violations_json(&g)),
+            ("grid", g.to_json()),
+        ]));
+        return exit_code(&g.status);
+    }
+    if report_conflicts(&mut g) {
+        println!("Grid is valid");
+    }
+    g.print(true);
+    exit_code(&g.status)
+}
 
-        // set up arrays row x state, and col x state
-        println!("Computing row+col 'state claimed' boolmap");
-        let mut rticked = [[false; MAXSTATES]; MAXSTATES];
-        let mut cticked = [[false; MAXSTATES]; MAXSTATES];
+fn cmd_rate(filename: &str, json: bool) -> i32 {
+    let mut g = cli_load(filename, json);
+    if g.status == GridStatus::Invalid {
+        if json {
+            println!("{}", Json::Obj(vec![
+                ("status", Json::str(format!("{:?}", g.status))),
+                ("conflicts", conflicts_json(&g, &g.conflicts())),
+                ("violations", violations_json(&g)),
+            ]));
+        } else {
+            report_conflicts(&mut g);
+        }
+        return exit_code(&g.status);
+    }
+    let (r, steps) = match g.rate_with_steps() {
+        Ok(rated) => rated,
+        Err(e) if json => return json_error(&e),
+        Err(e) => {
+            println!("Error: {}", e);
+            return error_code(&e);
+        }
+    };
+    let status = rate_status(&g, &r);
+    if json {
+        println!("{}", Json::Obj(vec![
+            ("status", Json::str(format!("{:?}", status))),
+            ("rating", r.to_json()),
+            ("steps", Json::Arr(steps.iter().map(|step| g.step_json(step)).collect())),
+        ]));
+    } else {
+        println!("{}", r);
+    }
+    exit_code(&status)
+}
 
-        // variables which simplifies expressions/readability
-        let n = self.states as usize;    // n = number of states (9 for Sudoku)
-        let bw = self.isqrt as usize;    // bw = box width (3 for Sudoku)
+// rate_status - status of a rated grid: Solved if logic finishes it, otherwise as solve
+// would find it (Unsolvable with no or several solutions), or Incomplete if it needs search
+fn rate_status(g: &Grid, r: &Rating) -> GridStatus {
+    if r.solved {
+        GridStatus::Solved
+    } else if g.count_solutions(2) != 1 {
+        GridStatus::Unsolvable
+    } else {
+        GridStatus::Incomplete
+    }
+}
 
-        // a) do one-off walk over grid to set row/col boolmaps
-        println!("{}","a) Set boolmaps".italic());
-        for row in 0..n {
-            for col in 0..n {
-                let address = row * n + col;
-                if self.cells[address].solved {
-                    let sol = self.cells[address].solution as usize;
-                    if rticked[row][sol] || cticked[col][sol] {
-                        panic!()
-                    }
-                    rticked[row][sol] = true;
-                    cticked[col][sol] = true;                    
-                    //println!("- {row},{col} occupied by {sol}");
-                }
+fn cmd_generate(args: &[&str], json: bool) -> i32 {
+    let mut size = None;
+    let mut seed = None;
+    let mut boxes = None;
+    let mut variants = Vec::new();
+    let mut shape = None;
+    let mut i = 0;
+    while i < args.len() {
+        let value = args.get(i + 1).copied().unwrap_or("");
+        match (args[i], value.parse::<u64>().ok()) {
+            ("--size", Some(v)) => size = Some(v as usize),
+            ("--seed", Some(v)) => seed = Some(v),
+            ("--boxes", _) => match value.split_once('x').map(|(w, h)| (w.parse::<usize>(), h.parse::<usize>())) {
+                Some((Ok(w), Ok(h))) => boxes = Some((w, h)),
+                _ => return usage(),
+            },
+            ("--variant", _) => match Variant::from_name(value) {
+                Some(v) => variants.push(v),
+                None => return usage(),
+            },
+            ("--shape", _) if value == "cube" || Polydoku::shape_named(value).is_some() => shape = Some(value),
+            _ => return usage(),
+        }
+        i += 2;
+    }
+    let seed = seed.unwrap_or_else(|| {
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+        now.as_nanos() as u64
+    });
+    if let Some(shape) = shape {
+        return generate_poly(size.unwrap_or(9), shape, seed, json);
+    }
+    // the size follows from the boxes if not given
+    let Some(size) = size.or(boxes.map(|(w, h)| w * h)) else {
+        return usage();
+    };
+
+    let empty = match boxes {
+        Some((w, h)) => Grid::with_boxes(default_states(size), w, h),
+        None => Grid::sized(size),
+    };
+    let generated = empty.and_then(|mut g| {
+        for &variant in &variants {
+            g.add_variant(variant)?;
+        }
+        Grid::generate_in(g, &mut Rng::new(seed))
+    });
+    match generated {
+        Ok(mut g) => {
+            g.name = format!("{} (seed {})", g.name, seed);
+            if json {
+                g.update_status();
+                // seeds are full u64, so kept as a string rather than lose precision
+                println!("{}", Json::Obj(vec![("seed", Json::str(seed.to_string())), ("grid", g.to_json())]));
+            } else {
+                print!("{}", g.to_sud());
             }
+            0
         }
+        Err(e) if json => json_error(&e),
+        Err(e) => {
+            println!("Error: {}", e);
+            error_code(&e)
+        }
+    }
+}
 
-        // b) do row based 'triple' rationalise (actually divided by isqrt)
-        // we do this by processing blocks across, then separately down
-        // a row is a candidate for completion of a state if it is in two blocks
-        // if so, we need to identify the target row of the missing block
-        // each of the slots in the row need to be checked for viability
-        // if only one is viable, then this cell can be claimed
-        println!("{}","b) 'triple' finder (under dev)".italic());
-        for grid_block in 0..bw {    // three of these (of 3) in Sudoku = 0,1,2
-            for state in 0..n {
-
-                // compile these values for state
-                let mut used = 0;
-                let mut rowusage = [false; MAXSTATES];
-                let mut slugmap = [[false; MAXROOTS]; MAXROOTS];
-
-                for row in 0..bw {  // row means a row element of blocks on horiz row i
-                    let start = (grid_block * bw + row) * n;
-                    //println!("Row {} ({})", row, start);
-                    for el in 0..n {    // across all cols whole row
-                        if self.cells[start+el].solved  && self.cells[start+el].solution==state as u8 {
-                            used += 1;
-                            rowusage[row]=true;
-                            let slug=el/bw; // integer divide
-                            slugmap[row][slug]=true;
-                        }
-                    }
-                }
-
-                // we are only interested in rows where n-1 blocks are already populated
-                //println!("Blk{} / state={} / used={} / slugs={:?}", grid_block, state,used,slugmap);
-                if used==2 {
-                    // find which row
-                    let mut target_row: usize = 0;
-                    for row in 0..bw {
-                        if !rowusage[row] {
-                            target_row=row;
-                            break;
-                        }
-                    }
-                    // find which block
-                    let mut target_block: usize = 0;
-                    for row in 0..bw {
-                        //println!("--check {:?}", slugmap[row]);
-                        if slugmap[row] == [false,false,false] {
-                            target_block = row;
-                            break;
-                        }
-                    }
-
-                    println!("Grid block #{} / State {} x 2 + none on row {} block {}", grid_block, state, target_row, target_block);
-                    // println!("   use={:?}",rowusage);
-                    // println!(" slugs={:?}",slugmap);
-               }
+// generate_poly - generate for --shape, a polydoku of sub-grids (or a cube of faces) of size states
+fn generate_poly(size: usize, shape: &str, seed: u64, json: bool) -> i32 {
+    match Polydoku::named(default_states(size), shape).and_then(|p| Polydoku::generate_in(p, &mut Rng::new(seed))) {
+        Ok(mut p) => {
+            p.name = format!("{} (seed {})", p.name, seed);
+            if json {
+                println!("{}", Json::Obj(vec![("seed", Json::str(seed.to_string())), ("polydoku", p.to_json())]));
+            } else {
+                print!("{}", p.to_text());
             }
-        
+            0
         }
+        Err(e) if json => json_error(&e),
+        Err(e) => {
+            println!("Error: {}", e);
+            error_code(&e)
+        }
+    }
+}
 
-
-//panic!();
-
-
-        // c) check boolmaps for '8/9' used ... by row
-        println!("{}","c) check boolmaps for '8/9' used ... by row".italic());
-        let mut used:usize = 0;
-        for row in 0..n {
-            used = 0;
-            print!("R{:2}: ",self.symbols[row]);
-            for sol in 0..n {
-                if rticked[row][sol] {
-                    print!("{}",self.symbols[sol]);
-                    used += 1;
-                }
-            }
-            // - see if can make immediate claim
-            if used == n-1 {
-                // which state is missing?
-                let mut missed : Snumb = 0;  // might actually be 0
-                for state in 0..n {
-                   if !rticked[row][state] {
-                        missed = state as Snumb;
-                        break;
-                   }
-                }
-                print!(" ... CLAIM - add {}\n",self.symbols[missed as usize]);
-                // where is the gap?
-                let mut address = 0; // init for compile
-                for col in 0..n {
-                    address = (row * n + col);
-                    if !self.cells[address].solved {
-                        self.claim_a(address, missed);
-                        return 1;
-                    }
-                }
-            }
-            println!()
-        }
-
-        // d) check boolmaps for '8/9' used ... by column
-        println!("{}","d) check boolmaps for '8/9' used ... by column".italic());
-        for col in 0..n {
-            used = 0;
-            print!("C{:2}: ",self.symbols[col]);
-            for sol in 0..n {
-                if cticked[col][sol] {
-                    print!("{}",self.symbols[sol]);
-                    used += 1;
-                }
+// cmd_poly - validate and solve a polydoku, showing the combined shape
+fn cmd_poly(filename: &str, json: bool) -> i32 {
+    let loaded = std::fs::read_to_string(filename)
+        .map_err(|e| GridError::Io { filename: filename.to_owned(), message: e.to_string() })
+        .and_then(|text| Polydoku::from_text(&text));
+    let mut p = match loaded {
+        Ok(p) => p,
+        Err(e) if json => return json_error(&e),
+        Err(e) => {
+            println!("Error: {}", e);
+            return error_code(&e);
+        }
+    };
+
+    let conflicts = p.validate();
+    let mut solutions = 0;
+    if conflicts.is_empty() {
+        match p.solve() {
+            Ok(count) => solutions = count,
+            Err(e) if json => return json_error(&e),
+            Err(e) => {
+                println!("Error: {}", e);
+                return error_code(&e);
             }
-            // - see if can male immediate claim
-            if used == n-1 {
-                // which state is missing?
-                let mut missed : Snumb = 0;  // might actually be 0
-                for state in 0..n {
-                   if !cticked[col][state] {
-                        missed = state as Snumb;
-                        break;
-                   }
-                }
-                print!(" ... CLAIM - add {}\n",self.symbols[missed as usize]);
-                // where is the gap?
-                let mut address = 0; // init for compile
-                for row in 0..n {
-                    address = row * n + col;
-                    if !self.cells[address].solved {
-                        self.claim_rc(row, col, missed);
-                        return 1;
-                    }
-                }
-            } 
-            println!()
         }
+        p.update_status();
+        if solutions != 1 {
+            p.status = GridStatus::Unsolvable;
+        }
+    }
 
-        // e) do block by block scan for 8/9 solved
-        println!("{}","e) do block by block scan for 8/9 solved".italic());
-        let mut bticked= [false; MAXSTATES];
-        let mut used:usize = 0;
-        for b in 0..n { // per block
-            for el in 0..n {
-                bticked[el as usize] = false;
-            }
+    if json {
+        println!("{}", Json::Obj(vec![
+            ("status", Json::str(format!("{:?}", p.status))),
+            ("solutions", Json::num(solutions)),
+            ("conflicts", Json::Arr(conflicts.iter().map(|c| p.conflict_json(c)).collect())),
+            ("polydoku", p.to_json()),
+        ]));
+        return exit_code(&p.status);
+    }
+    if !conflicts.is_empty() {
+        println!("{}","Validating grid".underline());
+        for conflict in &conflicts {
+            println!("{}", p.conflict_text(conflict));
+        }
+    } else if solutions == 0 {
+        println!("No solution");
+    } else if solutions > 1 {
+        println!("More than one solution");
+    }
+    p.print();
+    exit_code(&p.status)
+}
 
-            // relative block offset
-            let bx = (b % bw) * bw;
-            let by = (b / bw) * bw * n;
-            //println!("\nblock {} for {}+{}", b, bx, by);
-
-            let  mut memx: usize = 0;   // (x,y) of last free cell
-            let  mut memy: usize = 0;
-            let  mut mema: usize = 0;
-            for y in 0..bw {
-                for x in 0..bw {
-                    let address = (bx + by + x + y*n) as usize;
-                    //print!("a={} ", address);
-                    if self.cells[address].solved {
-                        let sol = self.cells[address].solution as usize;
-                        if bticked[sol] {
-                            // should not be possible - would mean dup solution
-                            panic!()
-                        }
-                        else {                     
-                            bticked[sol] = true;
-                        }
-                    } else {
-                        mema = address;
-                        //print!("[save {}] ",address)
-                    }
-                }
+fn cmd_convert(input: &str, output: &str, json: bool) -> i32 {
+    let g = cli_load(input, json);
+    if !json {
+        println!("Saving file {}", output);
+        return match g.save(output.to_owned()) {
+            Ok(()) => 0,
+            Err(e) => {
+                println!("Error: {}", e);
+                error_code(&e)
             }
+        };
+    }
+    match g.save(output.to_owned()) {
+        Ok(()) => {
+            println!("{}", Json::Obj(vec![("saved", Json::str(output)), ("grid", g.to_json())]));
+            0
+        }
+        Err(e) => json_error(&e),
+    }
+}
 
-            used = 0;
-            for el in 0..n {
-                if bticked[el] {
-                    used += 1;
-                }
+fn cmd_canon(filename: &str, json: bool) -> i32 {
+    let g = cli_load(filename, json);
+    match (g.canonical(), g.canonical_hash()) {
+        (Ok(mut c), Ok(hash)) => {
+            if json {
+                c.update_status();
+                println!("{}", Json::Obj(vec![("hash", Json::str(format!("{:016x}", hash))), ("grid", c.to_json())]));
+            } else {
+                println!("{:016x}", hash);
+                print!("{}", c.to_sud());
             }
-            if used == n-1 {
-                // which state is missing?
-                let mut missed : Snumb = 0;  // might actually be 0
-                for state in 0..n {
-                   if !bticked[state] {
-                        missed = state as Snumb;
-                        break;
-                   }
-                }
-                print!("CLAIM - add {} to {}\n",self.symbols[missed as usize], mema);
-                //panic!();
-            
-                if !self.cells[mema].solved {
-                    self.claim_a(mema, missed);
-                    return 1;
-                } else {
-                    panic!()
-                }
+            0
+        }
+        (Err(e), _) | (_, Err(e)) if json => json_error(&e),
+        (Err(e), _) | (_, Err(e)) => {
+            println!("Error: {}", e);
+            error_code(&e)
+        }
+    }
+}
 
+fn cmd_minimise(filename: &str, json: bool) -> i32 {
+    let mut g = cli_load(filename, json);
+    match g.minimise() {
+        Ok(redundant) if json => {
+            let removed = redundant.iter().map(|&address| Json::Obj(vec![
+                ("row", Json::num(address / g.states as usize + 1)),
+                ("col", Json::num(address % g.states as usize + 1)),
+                ("value", Json::str(g.symbols[g.cells[address].solution as usize])),
+            ])).collect();
+            println!("{}", Json::Obj(vec![("redundant", Json::Arr(removed)), ("grid", g.to_json())]));
+            0
+        }
+        Ok(redundant) => {
+            println!("{} redundant givens:", redundant.len());
+            for address in redundant {
+                let sym = g.symbols[g.cells[address].solution as usize];
+                println!("- '{}' at {}", sym, g.cell_name(address));
             }
-
-
+            print!("{}", g.to_sud());
+            0
+        }
+        Err(e) if json => json_error(&e),
+        Err(e) => {
+            println!("Error: {}", e);
+            error_code(&e)
         }
-
-
-
-
-
-
-        added
     }
+}
 
+// batch_puzzles - (name, text) of each puzzle in a directory of .sud files (in name
+// order), or in a file of one-line puzzles (blank lines and '#' comments skipped)
+// any other file is taken as a single .sud puzzle
+fn batch_puzzles(path: &str) -> std::io::Result<Vec<(String, String)>> {
+    let mut puzzles = Vec::new();
+
+    if std::path::Path::new(path).is_dir() {
+        let mut files: Vec<std::path::PathBuf> = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|ext| ext == "sud"))
+            .collect();
+        files.sort();
+        for file in files {
+            let name = file.file_name().unwrap().to_string_lossy().into_owned();
+            puzzles.push((name, std::fs::read_to_string(&file)?));
+        }
+        return Ok(puzzles);
+    }
 
+    let text = std::fs::read_to_string(path)?;
+    // a .sud file is one puzzle, even when its title reads as a one-line puzzle
+    let first = text.lines().find(|l| !l.trim().is_empty() && !l.starts_with('#')).unwrap_or("");
+    if path.ends_with(".sud") || Grid::from_line(first, path).is_err() {
+        puzzles.push((path.to_owned(), text));
+        return Ok(puzzles);
+    }
+    for (number, line) in text.lines().enumerate() {
+        if !line.trim().is_empty() && !line.starts_with('#') {
+            puzzles.push((format!("{}:{}", path, number + 1), line.to_owned()));
+        }
+    }
+    Ok(puzzles)
+}
 
-
-
-
-
-
-    // print - write grid to stdout
-    fn print(&self, write_header:bool) {
-        // calculate solved cell count
-        let mut used: u32 = 0;
-        let total: usize = self.cells.len();
-        for i in 0..total {
-            if self.cells[i].solved {
-                used += 1;
-            }
+// batch_one - load, rate and solve one puzzle of a batch, giving the grid (solved if
+// possible, with its status set), the number of givens and the rating if it is unique
+fn batch_one(text: &str, name: &str) -> Result<(Grid, usize, Option<Rating>), GridError> {
+    let mut g = Grid::from_sud(text, name)?;
+    let givens = g.cells.iter().filter(|c| c.solved).count();
+    let mut rating = None;
+    g.update_status();
+    if g.status == GridStatus::Incomplete || g.status == GridStatus::Solved {
+        if g.count_solutions(2) == 1 {
+            rating = Some(g.rate()?);
+            g.solve()?;
+            g.update_status();
+        } else {
+            g.status = GridStatus::Unsolvable;
         }
+    }
+    Ok((g, givens, rating))
+}
 
-        // write out header
-        if write_header {
-            println!("{} {}/{}", self.name, used, total);
+fn cmd_batch(args: &[&str], json: bool) -> i32 {
+    let (path, timed) = match args {
+        [path] => (*path, true),
+        [path, "--no-time"] => (*path, false),
+        _ => return usage(),
+    };
+    let puzzles = match batch_puzzles(path) {
+        Ok(p) => p,
+        Err(e) if json => return json_error(&GridError::Io { filename: path.to_owned(), message: e.to_string() }),
+        Err(e) => {
+            println!("Unable to read {}: {}", path, e);
+            return EXIT_ERROR;
         }
+    };
 
-        // write out cells
-        for i in 0..total {
-            if i != 0 {
-                if i % self.states as usize == 0 as usize {
-                    println!("");
-                    if i % (self.isqrt * self.states) as usize == 0 as usize {
-                        println!("");
-                    }
-                } else if i % self.isqrt as usize == 0 as usize {
-                    print!("   ");
+    if !json {
+        println!("{:<28} {:>7} {:>6}  {:<10} {:<10} {:>9}  techniques", "name", "size", "givens", "status", "rating", "ms");
+    }
+
+    let mut code = 0;
+    let mut statuses: Vec<(String, usize)> = Vec::new();
+    let mut uses = vec![0; TECHNIQUES.len()];
+    let mut results = Vec::new();
+    let batch_start = std::time::Instant::now();
+    for (name, text) in &puzzles {
+        let start = std::time::Instant::now();
+        let (g, givens, rating) = match batch_one(text, name) {
+            Ok(done) => done,
+            Err(e) => {
+                // a bad puzzle is reported in its row, and the batch carries on
+                if json {
+                    results.push(Json::Obj(vec![("name", Json::str(name.as_str())), ("error", Json::str(e.to_string()))]));
+                } else {
+                    println!("{:<28} {:>7} {:>6}  {:<10} {}", name, "-", "-", "Error", e);
                 }
+                match statuses.iter_mut().find(|(s, _)| s == "Error") {
+                    Some((_, count)) => *count += 1,
+                    None => statuses.push(("Error".to_owned(), 1)),
+                }
+                code = code.max(EXIT_ERROR);
+                continue;
             }
-
-            if self.cells[i].solved {
-                // trying symbols rather than .chars().nth()  [still messy]
-                //print!(" {} ", self.state_dict.chars().nth(self.cells[i].solution as usize).unwrap());
-                let sym = format!("{}", self.symbols[(self.cells[i].solution) as usize]);
-                match self.cells[i].highlight {
-                1 => print!(" {} ", sym.green().bold()),
-                2 => print!(" {} ", sym.red().bold()),
-                _ => print!(" {} ", sym),
+        };
+        let millis = start.elapsed().as_secs_f64() * 1000.0;
+
+        let mut histogram = Vec::new();
+        if let Some(r) = &rating {
+            for (k, t) in TECHNIQUES.iter().enumerate() {
+                if r.uses[k] > 0 {
+                    histogram.push(format!("{}:{}", t.name(), r.uses[k]));
+                    uses[k] += r.uses[k];
                 }
-                //print!(" {} ", sym.green().bold());
-                //print!(" {} ", sym);
-            } else {
-                print!(" - ");
             }
         }
-        println!()
-    }
-
-    // tab - write grid to stdout as tabs in 3-line format
-    fn tab(&self) {
-        // calculate solved cell count
-        let mut used: u32 = 0;
-        let total: usize = self.cells.len();
-        for i in 0..total {
-            if self.cells[i].solved {
-                used += 1;
-            }
+        let status = format!("{:?}", g.status);
+        if json {
+            results.push(Json::Obj(vec![
+                ("name", Json::str(name.as_str())),
+                ("states", Json::num(g.states as usize)),
+                ("givens", Json::num(givens)),
+                ("status", Json::str(status.as_str())),
+                ("rating", rating.as_ref().map_or(Json::Null, |r| r.to_json())),
+                ("ms", if timed { Json::Num((millis * 1000.0).round() / 1000.0) } else { Json::Null }),
+            ]));
+        } else {
+            let row = format!("{:<28} {:>7} {:>6}  {:<10} {:<10} {:>9}  {}",
+                              name,
+                              format!("{}x{}", g.states, g.states),
+                              givens,
+                              status,
+                              rating.as_ref().map_or("-", |r| r.grade()),
+                              if timed { format!("{:.3}", millis) } else { "-".to_owned() },
+                              histogram.join(" "));
+            println!("{}", row.trim_end());
         }
-        println!("{} {}/{}", self.name, used, total);
 
-        let row1 = "".to_string(); // possible row
-        let row2 = "".to_string(); // solution row
-        let row3 = "".to_string(); // disallowed row
+        match statuses.iter_mut().find(|(s, _)| *s == status) {
+            Some((_, count)) => *count += 1,
+            None => statuses.push((status, 1)),
+        }
+        code = code.max(exit_code(&g.status));
+    }
 
-        for i in 0..total {
-            if i != 0 {
-                if i % self.states as usize == 0 as usize {
-                    println!("");
-                    if i % (self.isqrt * self.states) as usize == 0 as usize {
-                        println!("");
-                    }
-                } else if i % self.isqrt as usize == 0 as usize {
-                    print!("   ");
-                }
-            }
+    // summary
+    if json {
+        let counts = statuses.iter().map(|(s, n)| Json::Obj(vec![("status", Json::str(s.as_str())), ("count", Json::num(*n))])).collect();
+        let totals = TECHNIQUES.iter().zip(&uses).filter(|&(_, &n)| n > 0).map(|(t, &n)| (t.name(), Json::num(n))).collect();
+        let seconds = (batch_start.elapsed().as_secs_f64() * 1000.0).round() / 1000.0;
+        println!("{}", Json::Obj(vec![
+            ("puzzles", Json::Arr(results)),
+            ("summary", Json::Obj(vec![
+                ("count", Json::num(puzzles.len())),
+                ("statuses", Json::Arr(counts)),
+                ("techniques", Json::Obj(totals)),
+                ("seconds", if timed { Json::Num(seconds) } else { Json::Null }),
+            ])),
+        ]));
+        return code;
+    }
+    let counts: Vec<String> = statuses.iter().map(|(s, n)| format!("{} {}", n, s)).collect();
+    println!("{} puzzles: {}", puzzles.len(), counts.join(", "));
+    let totals: Vec<String> = TECHNIQUES.iter().zip(&uses)
+        .filter(|&(_, &n)| n > 0)
+        .map(|(t, n)| format!("{}:{}", t.name(), n))
+        .collect();
+    println!("techniques: {}", totals.join(" "));
+    if timed {
+        println!("time: {:.3}s", batch_start.elapsed().as_secs_f64());
+    }
+    code
+}
 
-            if self.cells[i].solved {
-                print!(
-                    " {} ",
-                    self.state_dict
-                        .chars()
-                        .nth(self.cells[i].solution as usize)
-                        .unwrap()
-                );
-            } else {
-                print!(" - ");
-            }
-        }
-        println!()
+// cmd_kernels - list the unit-scan kernels, marking the one in use
+fn cmd_kernels() -> i32 {
+    let current = Kernel::current();
+    for k in KERNELS {
+        let note = if k == current { "in use" } else if k.available() { "available" } else { "-" };
+        println!("{:<8} {}", k.name(), note);
     }
+    0
 }
 
-fn main() {
+// demo - the original fixed demo
+fn demo() {
     // Create an empty grid
     let mut g = Grid::new("123456789");
     if !g.isempty() {
@@ -716,7 +847,7 @@ fn main() {
 
     // Load grid with test data
     #[rustfmt::skip]
-    let demox = vec![
+    let _demox = vec![
         7, 0, 0,  0, 0, 0,  0, 0, 3, 
         0, 0, 0,  5, 7, 0,  0, 0, 0, 
         0, 6, 0,  0, 3, 1,  0, 0, 0, 
@@ -760,7 +891,7 @@ fn main() {
     g.print(true);
 
     // do a validate
-    if (!g.validate()) {
+    if !report_conflicts(&mut g) {
         println!("Grid is not valid");
         g.print(false);
         std::process::exit(1)
@@ -769,18 +900,58 @@ fn main() {
         println!("It's fine");
     }
 
-    while g.solve_next() >0 {
-        g.print(false);
+    loop {
+        match g.solve_next() {
+            Ok(0) => break,
+            Ok(_) => g.print(false),
+            Err(e) => {
+                println!("Error: {}", e);
+                break;
+            }
+        }
     }
-    
-    // g.load("test2a.sud".to_owned());
-    // g.validate();
-    // g.save("test2aupd.sud".to_owned());
-
-    // g.load("test2b.sud".to_owned());
-    // g.validate();
-    // g.save("test2bupd.sud".to_owned());
+}
 
-    // println!("{}", g);
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let json = args.iter().any(|a| a == "--json");
+    let mut words: Vec<&str> = args.iter().map(|a| a.as_str()).filter(|&a| a != "--json").collect();
+
+    if let Some(i) = words.iter().position(|&w| w == "--kernel") {
+        let Some(kernel) = words.get(i + 1).and_then(|name| Kernel::from_name(name)) else {
+            std::process::exit(usage());
+        };
+        if let Err(e) = kernel.select() {
+            println!("Error: {}", e);
+            std::process::exit(error_code(&e));
+        }
+        words.drain(i..i + 2);
+    }
 
+    let code = match words.as_slice() {
+        ["solve", file] => cmd_solve(file, json),
+        ["validate", file] => cmd_validate(file, json),
+        ["rate", file] => cmd_rate(file, json),
+        ["generate", rest @ ..] => cmd_generate(rest, json),
+        ["convert", input, output] => cmd_convert(input, output, json),
+        ["canon", file] => cmd_canon(file, json),
+        ["minimise", file] => cmd_minimise(file, json),
+        ["batch", rest @ ..] => cmd_batch(rest, json),
+        ["poly", file] => cmd_poly(file, json),
+        ["repl"] => {
+            repl(None);
+            0
+        }
+        ["repl", file] => {
+            repl(Some(file.to_string()));
+            0
+        }
+        ["kernels"] => cmd_kernels(),
+        ["demo"] => {
+            demo();
+            0
+        }
+        _ => usage(),
+    };
+    std::process::exit(code);
 }
@@ -0,0 +1,666 @@
+// Polydoku - several square sub-grids overlapping on whole boxes (Twodoku, Samurai,
+// Butterfly, Flower, Sohei), or the three faces of a cube sharing their edges, solved
+// together so that a shared cell answers to the rows, columns and blocks of every
+// sub-grid holding it
+// cells are addressed across the whole canvas (row * width + col), and the gaps between
+// sub-grids belong to no unit - each sub-grid keeps the canvas address of each of its
+// cells, so that a cube face can be folded onto its neighbour's edge
+
+use std::sync::Arc;
+
+use colored::Colorize;
+
+use crate::constraint::Constraint;
+use crate::{Cell, Conflict, Grid, GridError, GridStatus, Json, Rng, Search, Snumb, Unit, Units, default_boxes};
+
+/// Polydoku is a puzzle of overlapping sub-grids of n states, laid out on one canvas
+#[derive(Clone)]
+pub struct Polydoku {
+    pub name: String,                 // e.g. "Samurai 1"
+    pub state_dict: String,           // e.g. "123456789"
+    pub status: GridStatus,
+    pub states: u8,                   // states of each sub-grid, also its width and height
+    pub box_w: u8,                    // columns per box
+    pub box_h: u8,                    // rows per box
+    pub shape: Vec<(usize, usize)>,   // (row, col) of each sub-grid, counted in boxes (none for a cube)
+    pub cube: bool,                   // faces of a cube (top, left, right) rather than laid out by shape
+    pub grids: Vec<Vec<usize>>,       // canvas address of each cell of each sub-grid, in its reading order
+    pub width: usize,                 // canvas columns
+    pub height: usize,                // canvas rows
+    pub present: Vec<bool>,           // whether each canvas cell is in a sub-grid
+    pub cells: Vec<Cell>,             // every canvas cell, in reading order
+    pub symbols: Vec<char>,
+    pub units: Arc<Units>,            // rows, columns and blocks of every sub-grid (shared blocks once)
+}
+
+/// Named shapes, as the (row, col) of each sub-grid counted in boxes
+pub const SHAPES: [(&str, &[(usize, usize)]); 5] = [
+    ("twodoku", &[(0, 0), (2, 2)]),
+    ("samurai", &[(0, 0), (0, 4), (2, 2), (4, 0), (4, 4)]),
+    ("butterfly", &[(0, 0), (0, 1), (1, 0), (1, 1)]),
+    ("flower", &[(0, 1), (1, 0), (1, 1), (1, 2), (2, 1)]),
+    ("sohei", &[(0, 2), (2, 0), (2, 4), (4, 2)]),
+];
+
+/// Faces of a cube, in the order of its sub-grids
+pub const FACES: [&str; 3] = ["top", "left", "right"];
+
+impl Polydoku {
+    /// new - empty puzzle of sub-grids with the given states, placed by shape
+    pub fn new(states: &str, shape: &[(usize, usize)]) -> Result<Polydoku, GridError> {
+        let (n, bw, bh) = sub_grid_size(states)?;
+        if shape.is_empty() {
+            return Err(GridError::Empty);
+        }
+
+        // top left cell of each sub-grid
+        let corners: Vec<(usize, usize)> = shape.iter().map(|&(r, c)| (r * bh, c * bw)).collect();
+        let height = corners.iter().map(|&(r, _)| r + n).max().unwrap_or(0);
+        let width = corners.iter().map(|&(_, c)| c + n).max().unwrap_or(0);
+        let grids = corners.iter()
+            .map(|&(top, left)| (0..n * n).map(|a| (top + a / n) * width + left + a % n).collect())
+            .collect();
+
+        let mut p = Polydoku::from_grids(states, grids, width, height)?;
+        p.shape = shape.to_vec();
+        Ok(p)
+    }
+
+    /// cube - empty puzzle on the three faces of a cube seen corner on, each a grid with
+    /// the given states: the top face's bottom edge is the right face's top edge, its left
+    /// edge the left face's top edge, and the left face's right edge the right face's left
+    /// edge, the cells along an edge belonging to both faces
+    /// on the canvas the right face hangs below the top one, sharing a row, and the left
+    /// face beside it, sharing a column - its top row being the top face's left column
+    pub fn cube(states: &str) -> Result<Polydoku, GridError> {
+        let (n, _, _) = sub_grid_size(states)?;
+        let side = 2 * n - 1;
+        let top = (0..n * n).map(|a| (a / n) * side + n - 1 + a % n).collect();
+        let left = (0..n * n)
+            .map(|a| if a < n { a * side + n - 1 } else { (n - 1 + a / n) * side + a % n })
+            .collect();
+        let right = (0..n * n).map(|a| (n - 1 + a / n) * side + n - 1 + a % n).collect();
+
+        let mut p = Polydoku::from_grids(states, vec![top, left, right], side, side)?;
+        p.name = format!("Empty cube for {}", states);
+        p.cube = true;
+        Ok(p)
+    }
+
+    /// named - empty puzzle with the given states of the shape with the given name (one of
+    /// SHAPES, positions as for shape_named, or `cube`)
+    pub fn named(states: &str, name: &str) -> Result<Polydoku, GridError> {
+        match name {
+            "cube" => Polydoku::cube(states),
+            _ => match Polydoku::shape_named(name) {
+                Some(shape) => Polydoku::new(states, &shape),
+                None => Err(GridError::UnknownShape(name.to_owned())),
+            },
+        }
+    }
+
+    // from_grids - empty puzzle of sub-grids with the given states, given the canvas
+    // address of each cell of each, on a canvas width by height
+    fn from_grids(states: &str, grids: Vec<Vec<usize>>, width: usize, height: usize) -> Result<Polydoku, GridError> {
+        let (n, bw, bh) = sub_grid_size(states)?;
+        let mut present = vec![false; width * height];
+        let mut units: Vec<(Unit, Vec<usize>)> = Vec::new();
+        // each sub-grid's own unit table, moved onto the canvas
+        let own = Units::standard(n, bw, bh);
+        for (g, addresses) in grids.iter().enumerate() {
+            for (&unit, cells) in own.kinds.iter().zip(&own.cells) {
+                let cells: Vec<usize> = cells.iter().map(|&a| addresses[a]).collect();
+                for &address in &cells {
+                    present[address] = true;
+                }
+                let unit = match unit {
+                    Unit::Row(i) => Unit::Row(g * n + i),
+                    Unit::Col(i) => Unit::Col(g * n + i),
+                    other => Unit::Block(g * n + other.index()),
+                };
+                // a block shared with an earlier sub-grid is only one unit
+                if !units.iter().any(|(_, c)| *c == cells) {
+                    units.push((unit, cells));
+                }
+            }
+        }
+
+        Ok(Polydoku {
+            name: format!("Empty polydoku for {}", states),
+            state_dict: states.to_owned(),
+            status: GridStatus::Empty,
+            states: n as u8,
+            box_w: bw as u8,
+            box_h: bh as u8,
+            shape: Vec::new(),
+            cube: false,
+            grids,
+            width,
+            height,
+            present,
+            cells: vec![Cell::empty(n as u8); width * height],
+            symbols: states.chars().collect(),
+            units: Arc::new(Units::from_units(width * height, units)),
+        })
+    }
+
+    /// shape_named - the shape with the given name, or a list of sub-grid positions in
+    /// boxes as `row,col;row,col;...` (None if a position is repeated)
+    pub fn shape_named(name: &str) -> Option<Vec<(usize, usize)>> {
+        if let Some((_, shape)) = SHAPES.iter().find(|(n, _)| *n == name) {
+            return Some(shape.to_vec());
+        }
+        let shape: Vec<(usize, usize)> = name.split(';')
+            .map(|pos| {
+                let (r, c) = pos.split_once(',')?;
+                Some((r.parse().ok()?, c.parse().ok()?))
+            })
+            .collect::<Option<_>>()?;
+        let repeated = shape.iter().enumerate().any(|(i, pos)| shape[..i].contains(pos));
+        (!repeated).then_some(shape)
+    }
+
+    /// from_text - puzzle from text in .sud style
+    /// line 1 is the title, then a config line `<shape> <blank> <states> [notes]` where
+    /// shape is one of SHAPES, positions as for shape_named or `cube`, then the cells of
+    /// the sub-grids in reading order across the whole canvas - any character which is
+    /// neither a state nor the blank is ignored, so the gaps can be left as spaces
+    /// a cube's faces are instead given side by side, top, left and right, each in full:
+    /// an edge cell given by one face may be blank on the other, but not differ
+    /// variants and constraint lines are not supported, and are refused rather than ignored
+    pub fn from_text(text: &str) -> Result<Polydoku, GridError> {
+        let mut lines = text.lines();
+        let title = lines.next().ok_or(GridError::Empty)?.trim().to_owned();
+        let config = lines.by_ref().find(|l| !l.trim().is_empty()).ok_or(GridError::Empty)?;
+        let tokens: Vec<&str> = config.split_whitespace().collect();
+        if tokens.len() < 3 || tokens[1].chars().count() != 1 {
+            return Err(GridError::UnknownShape(config.trim().to_owned()));
+        }
+        if let Some(extra) = tokens.get(3) {
+            return Err(GridError::BadConfig(extra.to_string()));
+        }
+        let blank = tokens[1].chars().next().unwrap_or('-');
+
+        let mut p = Polydoku::named(tokens[2], tokens[0])?;
+        p.name = title;
+
+        let lines: Vec<&str> = lines.collect();
+        if lines.iter().any(|l| Constraint::parse(l, p.states as usize).is_some()) {
+            return Err(GridError::NotForLayout("a constraint"));
+        }
+
+        // populate the cells of the sub-grids in reading order
+        let addresses = p.text_order();
+        let mut k = 0;
+        for ch in lines.iter().flat_map(|l| l.chars()) {
+            if k == addresses.len() {
+                break;
+            }
+            if ch == blank {
+                k += 1;
+            } else if let Some(sol) = p.symbols.iter().position(|&s| s == ch) {
+                let name = p.cell_name(addresses[k]);
+                let cell = &mut p.cells[addresses[k]];
+                if cell.solved && cell.solution != sol as Snumb {
+                    return Err(GridError::EdgeMismatch(name));
+                }
+                cell.solved = true;
+                cell.given = true;
+                cell.solution = sol as Snumb;
+                k += 1;
+            }
+        }
+        if k != addresses.len() {
+            return Err(GridError::WrongLength { expected: addresses.len(), got: k });
+        }
+
+        p.update_status();
+        Ok(p)
+    }
+
+    // text_order - canvas address of each cell as from_text reads them: across the whole
+    // canvas, or for a cube along each row of the faces side by side (edge cells twice)
+    fn text_order(&self) -> Vec<usize> {
+        let n = self.states as usize;
+        if self.cube {
+            (0..n).flat_map(|row| self.grids.iter().flat_map(move |g| g[row * n..(row + 1) * n].iter().copied())).collect()
+        } else {
+            (0..self.present.len()).filter(|&a| self.present[a]).collect()
+        }
+    }
+
+    /// shape_name - the shape as given in the config line: a name from SHAPES, the
+    /// positions of the sub-grids, or `cube`
+    pub fn shape_name(&self) -> String {
+        if self.cube {
+            return "cube".to_owned();
+        }
+        match SHAPES.iter().find(|(_, s)| *s == self.shape.as_slice()) {
+            Some((name, _)) => name.to_string(),
+            None => {
+                let positions: Vec<String> = self.shape.iter().map(|(r, c)| format!("{},{}", r, c)).collect();
+                positions.join(";")
+            }
+        }
+    }
+
+    // face_lines - the rows of a cube's faces side by side, each cell drawn by cell, boxes
+    // spaced apart by gap and faces further, with a blank line between bands of boxes
+    fn face_lines(&self, gap: &str, cell: impl Fn(usize) -> String) -> Vec<String> {
+        let (n, bw, bh) = (self.states as usize, self.box_w as usize, self.box_h as usize);
+        let mut lines = Vec::new();
+        for row in 0..n {
+            if row != 0 && row % bh == 0 {
+                lines.push(String::new());
+            }
+            let faces: Vec<String> = self.grids.iter().map(|g| {
+                let cells: Vec<String> = (0..n).map(|col| cell(g[row * n + col])).collect();
+                cells.chunks(bw).map(|b| b.join("")).collect::<Vec<String>>().join(gap)
+            }).collect();
+            lines.push(faces.join("      ").trim_end().to_owned());
+        }
+        lines
+    }
+
+    /// to_text - puzzle as text for from_text, the gaps left as spaces
+    pub fn to_text(&self) -> String {
+        let blank = if self.symbols.contains(&'-') { '.' } else { '-' };
+        let mut text = format!("{}\n{} {} {}\n", self.name, self.shape_name(), blank, self.state_dict);
+        if self.cube {
+            text.push('\n');
+            let symbol = |a: usize| {
+                let cell = &self.cells[a];
+                format!(" {}", if cell.solved { self.symbols[cell.solution as usize] } else { blank })
+            };
+            for line in self.face_lines("  ", symbol) {
+                text.push_str(line.trim_start());
+                text.push('\n');
+            }
+            return text;
+        }
+        for row in 0..self.height {
+            if row % self.box_h as usize == 0 {
+                text.push('\n');
+            }
+            let mut line = String::new();
+            for col in 0..self.width {
+                if col != 0 {
+                    line.push_str(if col % self.box_w as usize == 0 { "   " } else { " " });
+                }
+                let address = row * self.width + col;
+                let cell = &self.cells[address];
+                line.push(if !self.present[address] {
+                    ' '
+                } else if cell.solved {
+                    self.symbols[cell.solution as usize]
+                } else {
+                    blank
+                });
+            }
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// sub_grid - sub-grid k on its own, as a Grid
+    pub fn sub_grid(&self, k: usize) -> Result<Grid, GridError> {
+        let mut g = Grid::with_boxes(&self.state_dict, self.box_w as usize, self.box_h as usize)?;
+        g.name = format!("{} ({})", self.name, self.grid_name(k));
+        for (cell, &address) in g.cells.iter_mut().zip(&self.grids[k]) {
+            *cell = self.cells[address].clone();
+        }
+        Ok(g)
+    }
+
+    /// grid_name - describe sub-grid k, as `grid <k+1>` or the face of a cube
+    pub fn grid_name(&self, k: usize) -> String {
+        if self.cube { format!("{} face", FACES[k]) } else { format!("grid {}", k + 1) }
+    }
+
+    /// conflicts - every state repeated within a unit of any sub-grid
+    pub fn conflicts(&self) -> Vec<Conflict> {
+        let mut conflicts = Vec::new();
+        for (u, unit) in self.units.cells.iter().enumerate() {
+            let mut holding = vec![Vec::new(); self.states as usize];
+            for &address in unit {
+                if self.cells[address].solved {
+                    holding[self.cells[address].solution as usize].push(address);
+                }
+            }
+            for (state, cells) in holding.into_iter().enumerate() {
+                if cells.len() > 1 {
+                    conflicts.push(Conflict { unit: self.units.kinds[u], state: state as Snumb, cells });
+                }
+            }
+        }
+        conflicts
+    }
+
+    /// validate - conflicts, with all the cells involved highlighted
+    pub fn validate(&mut self) -> Vec<Conflict> {
+        let conflicts = self.conflicts();
+        for conflict in &conflicts {
+            for &address in &conflict.cells {
+                self.cells[address].highlight = 2;
+            }
+        }
+        conflicts
+    }
+
+    /// conflict_text - describe a conflict for the user
+    pub fn conflict_text(&self, conflict: &Conflict) -> String {
+        let cells: Vec<String> = conflict.cells.iter().map(|&a| self.cell_name(a)).collect();
+        format!("'{}' repeated in {}: {}", self.symbols[conflict.state as usize],
+                self.unit_name(conflict.unit), cells.join(", "))
+    }
+
+    /// unit_name - describe a unit as the row, column or block of its sub-grid
+    pub fn unit_name(&self, unit: Unit) -> String {
+        let n = self.states as usize;
+        format!("{} {} of {}", unit.kind(), unit.index() % n + 1, self.grid_name(unit.index() / n))
+    }
+
+    /// cell_name - describe address as `r<row>c<col>` across the whole canvas, or on a
+    /// cube as `<face> r<row>c<col>` on the first face holding it
+    pub fn cell_name(&self, address: usize) -> String {
+        let n = self.states as usize;
+        let on_face = self.grids.iter().enumerate()
+            .find_map(|(k, g)| g.iter().position(|&a| a == address).map(|p| (k, p)));
+        match on_face {
+            Some((k, p)) if self.cube => format!("{} r{}c{}", FACES[k], p / n + 1, p % n + 1),
+            _ => format!("r{}c{}", address / self.width + 1, address % self.width + 1),
+        }
+    }
+
+    /// candidates - bitmask of the states still possible in each cell, from every unit
+    /// holding it - so a cell shared by two sub-grids answers to both (0 for solved cells
+    /// and gaps)
+    pub fn candidates(&self) -> Vec<u64> {
+        let all = if self.states == 64 { u64::MAX } else { (1u64 << self.states) - 1 };
+        let used: Vec<u64> = self.units.cells.iter()
+            .map(|unit| unit.iter().filter(|&&a| self.cells[a].solved).fold(0, |m, &a| m | 1u64 << self.cells[a].solution))
+            .collect();
+        (0..self.cells.len())
+            .map(|address| {
+                if !self.present[address] || self.cells[address].solved {
+                    return 0;
+                }
+                all & !self.units.of_cell[address].iter().fold(0, |m, &u| m | used[u])
+            })
+            .collect()
+    }
+
+    // search - brute-force search set up from the solved cells
+    // the gaps are passed as solved (with any state), as they belong to no unit
+    fn search(&self) -> Result<Search, GridError> {
+        let values: Vec<Option<Snumb>> = (0..self.cells.len())
+            .map(|a| if !self.present[a] { Some(0) } else { self.cells[a].solved.then_some(self.cells[a].solution) })
+            .collect();
+        Search::from_values(self.states as usize, &self.units, &[], &values, &self.symbols)
+    }
+
+    /// count_solutions - brute-force count of the solutions, stopping once limit is reached
+    pub fn count_solutions(&self, limit: usize) -> usize {
+        match self.search() {
+            Ok(mut s) => {
+                s.limit = limit;
+                s.run();
+                s.solutions
+            }
+            Err(_) => 0,
+        }
+    }
+
+    /// solve - brute-force fill in the first solution found (new cells highlighted)
+    /// returns number of solutions, up to 2 - anything other than 1 leaves the puzzle alone
+    pub fn solve(&mut self) -> Result<usize, GridError> {
+        let mut s = self.search()?;
+        s.limit = 2;
+        s.run();
+        if s.solutions == 1 {
+            for address in 0..self.cells.len() {
+                let cell = &mut self.cells[address];
+                if self.present[address] && !cell.solved {
+                    cell.solved = true;
+                    cell.solution = s.first[address] - 1;
+                    cell.highlight = 1;
+                }
+            }
+        }
+        Ok(s.solutions)
+    }
+
+    /// generate - new minimal puzzle of the given shape, using the random number generator
+    /// a random full fill is found by search, then givens removed in random order while
+    /// the solution stays unique
+    pub fn generate(states: &str, shape: &[(usize, usize)], rng: &mut Rng) -> Result<Polydoku, GridError> {
+        Polydoku::generate_in(Polydoku::new(states, shape)?, rng)
+    }
+
+    /// generate_in - new minimal puzzle on the empty puzzle p, keeping its states and shape
+    pub fn generate_in(mut p: Polydoku, rng: &mut Rng) -> Result<Polydoku, GridError> {
+        p.name = if p.cube { "Generated cube".to_owned() } else { format!("Generated polydoku of {} grids", p.grids.len()) };
+
+        let mut s = p.search()?;
+        s.random = Some(rng.clone());
+        s.run();
+        *rng = s.random.take().unwrap_or_else(|| rng.clone());
+        let mut order: Vec<usize> = (0..p.cells.len()).filter(|&a| p.present[a]).collect();
+        for &address in &order {
+            let cell = &mut p.cells[address];
+            cell.solved = true;
+            cell.given = true;
+            cell.solution = s.first[address] - 1;
+        }
+
+        rng.shuffle(&mut order);
+        for address in order {
+            p.cells[address].solved = false;
+            if p.count_solutions(2) != 1 {
+                p.cells[address].solved = true;
+            } else {
+                p.cells[address].given = false;
+            }
+        }
+        p.update_status();
+        Ok(p)
+    }
+
+    /// update_status - set status from the cells
+    pub fn update_status(&mut self) {
+        self.status = if !self.conflicts().is_empty() {
+            GridStatus::Invalid
+        } else if (0..self.cells.len()).all(|a| !self.present[a] || self.cells[a].solved) {
+            GridStatus::Solved
+        } else {
+            GridStatus::Incomplete
+        };
+    }
+
+    /// print - write the puzzle to stdout, the sub-grids in place with gaps between
+    pub fn print(&self) {
+        let used = (0..self.cells.len()).filter(|&a| self.present[a] && self.cells[a].solved).count();
+        let total = self.present.iter().filter(|&&p| p).count();
+        println!("{} {}/{}", self.name, used, total);
+
+        let draw = |cell: &Cell| {
+            if cell.solved {
+                let sym = self.symbols[cell.solution as usize].to_string();
+                let sym = match cell.highlight {
+                    1 => sym.green().bold().to_string(),
+                    2 => sym.red().bold().to_string(),
+                    _ => sym,
+                };
+                format!(" {} ", sym)
+            } else {
+                " - ".to_owned()
+            }
+        };
+
+        // a cube's faces side by side, each edge cell drawn on both faces sharing it
+        if self.cube {
+            let n = self.states as usize;
+            let faces: Vec<String> = FACES.iter().map(|f| format!("{:<1$}", f, 3 * n + 2)).collect();
+            println!("{}", faces.join("      ").trim_end());
+            for line in self.face_lines("   ", |a| draw(&self.cells[a])) {
+                println!("{}", line);
+            }
+            return;
+        }
+
+        let (bw, bh) = (self.box_w as usize, self.box_h as usize);
+        for row in 0..self.height {
+            if row != 0 && row % bh == 0 {
+                println!();
+            }
+            let mut line = String::new();
+            for col in 0..self.width {
+                if col != 0 && col % bw == 0 {
+                    line.push_str("   ");
+                }
+                let address = row * self.width + col;
+                if !self.present[address] {
+                    line.push_str("   ");
+                } else {
+                    line.push_str(&draw(&self.cells[address]));
+                }
+            }
+            println!("{}", line.trim_end());
+        }
+    }
+
+    /// to_json - the puzzle with status, shape and each cell of the sub-grids
+    pub fn to_json(&self) -> Json {
+        let cells = (0..self.cells.len()).filter(|&a| self.present[a]).map(|address| {
+            let c = &self.cells[address];
+            Json::Obj(vec![
+                ("row", Json::num(address / self.width + 1)),
+                ("col", Json::num(address % self.width + 1)),
+                ("given", Json::Bool(c.given)),
+                ("solved", Json::Bool(c.solved)),
+                ("value", if c.solved { Json::str(self.symbols[c.solution as usize]) } else { Json::Null }),
+            ])
+        }).collect();
+        // each sub-grid by the canvas cell of its top left corner (and a cube's by face)
+        let grids = self.grids.iter().enumerate().map(|(k, g)| {
+            let mut grid = vec![("row", Json::num(g[0] / self.width + 1)), ("col", Json::num(g[0] % self.width + 1))];
+            if self.cube {
+                grid.insert(0, ("face", Json::str(FACES[k])));
+            }
+            Json::Obj(grid)
+        }).collect();
+        Json::Obj(vec![
+            ("name", Json::str(self.name.as_str())),
+            ("state_dict", Json::str(self.state_dict.as_str())),
+            ("states", Json::num(self.states as usize)),
+            ("width", Json::num(self.width)),
+            ("height", Json::num(self.height)),
+            ("shape", Json::str(self.shape_name())),
+            ("grids", Json::Arr(grids)),
+            ("status", Json::str(format!("{:?}", self.status))),
+            ("cells", Json::Arr(cells)),
+        ])
+    }
+
+    /// conflict_json - a conflict, with the unit numbered within its sub-grid
+    pub fn conflict_json(&self, conflict: &Conflict) -> Json {
+        let n = self.states as usize;
+        let cells = conflict.cells.iter().map(|&address| Json::Obj(vec![
+            ("row", Json::num(address / self.width + 1)),
+            ("col", Json::num(address % self.width + 1)),
+        ])).collect();
+        Json::Obj(vec![
+            ("grid", Json::num(conflict.unit.index() / n + 1)),
+            ("unit", Json::str(conflict.unit.kind())),
+            ("index", Json::num(conflict.unit.index() % n + 1)),
+            ("value", Json::str(self.symbols[conflict.state as usize])),
+            ("cells", Json::Arr(cells)),
+        ])
+    }
+}
+
+// sub_grid_size - the states of each sub-grid, with its box width and height, for the
+// states given (NotSquare if they can't be divided into boxes)
+fn sub_grid_size(states: &str) -> Result<(usize, usize, usize), GridError> {
+    let n = states.chars().count();
+    let Some((bw, bh)) = default_boxes(n) else {
+        return Err(GridError::NotSquare { states: n });
+    };
+    if n > 64 {
+        return Err(GridError::TooLarge { states: n, max: 64 });
+    }
+    Ok((n, bw, bh))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // twodoku - text of an empty twodoku, with the config line given
+    fn twodoku(config: &str, rest: &str) -> String {
+        let mut text = format!("Twodoku\n{}\n", config);
+        for row in 0..15 {
+            let cells = if row < 6 { "---------" } else if row < 9 { "---------------" } else { "      ---------" };
+            text += &format!("{}\n", cells);
+        }
+        text + rest
+    }
+
+    #[test]
+    fn from_text_refuses_what_it_would_ignore() {
+        assert!(Polydoku::from_text(&twodoku("twodoku - 123456789", "")).is_ok());
+        assert_eq!(Polydoku::from_text(&twodoku("twodoku - 123456789 +diagonals", "")).err(),
+                   Some(GridError::BadConfig("+diagonals".to_owned())));
+        assert_eq!(Polydoku::from_text(&twodoku("twodoku - 123456789", "cage 15 r1c1 r1c2 r2c1\n")).err(),
+                   Some(GridError::NotForLayout("a constraint")));
+    }
+
+    #[test]
+    fn cube_corner_is_on_all_three_faces() {
+        let p = Polydoku::cube("123456789").unwrap();
+        let shared: Vec<usize> = p.grids[0].iter().copied()
+            .filter(|a| p.grids[1].contains(a) && p.grids[2].contains(a))
+            .collect();
+        assert_eq!(shared, vec![p.grids[0][72]]);
+        // top bottom left, left top right, right top left
+        assert_eq!((p.grids[1][8], p.grids[2][0]), (shared[0], shared[0]));
+        // the line along each of the three edges, and a block on each face
+        assert_eq!(p.units.of_cell[shared[0]].len(), 6);
+    }
+
+    #[test]
+    fn cube_edge_given_differently_is_a_mismatch() {
+        let path = format!("{}/sudfiles/cube1.sud", env!("CARGO_MANIFEST_DIR"));
+        let text = std::fs::read_to_string(path).unwrap();
+        assert!(Polydoku::from_text(&text).is_ok());
+        // the first cell of the top face's first row is the first of the left face's
+        let mut lines: Vec<String> = text.lines().map(|l| l.to_owned()).collect();
+        let give = |line: &str, top: char, left: char| {
+            let mut k = 0;
+            line.chars().map(|ch| {
+                let cell = ch != ' ';
+                k += cell as usize;
+                match (cell, k) {
+                    (true, 1) => top,
+                    (true, 10) => left,
+                    _ => ch,
+                }
+            }).collect::<String>()
+        };
+        let first = lines[3].clone();
+        lines[3] = give(&first, '1', '-');
+        assert!(Polydoku::from_text(&lines.join("\n")).is_ok());
+        lines[3] = give(&first, '1', '1');
+        assert!(Polydoku::from_text(&lines.join("\n")).is_ok());
+        lines[3] = give(&first, '1', '2');
+        assert!(matches!(Polydoku::from_text(&lines.join("\n")), Err(GridError::EdgeMismatch(_))));
+    }
+
+    #[test]
+    fn shape_named_refuses_a_repeated_position() {
+        assert_eq!(Polydoku::shape_named("0,0;2,2"), Some(vec![(0, 0), (2, 2)]));
+        assert_eq!(Polydoku::shape_named("0,0;0,0"), None);
+        assert!(Polydoku::named("123456789", "0,0;2,2;0,0").is_err());
+    }
+}
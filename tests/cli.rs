@@ -0,0 +1,152 @@
+// Runs the sudoku binary on the sample files and checks what it prints and
+// the exit code it gives.
+
+use std::process::Command;
+
+// run - stdout and exit code of the binary with the given arguments
+fn run(args: &[&str]) -> (String, i32) {
+    let out = Command::new(env!("CARGO_BIN_EXE_sudoku"))
+        .args(args)
+        .output()
+        .unwrap();
+    (String::from_utf8(out.stdout).unwrap(), out.status.code().unwrap())
+}
+
+#[test]
+fn solve_json_document() {
+    let (out, code) = run(&["solve", "sudfiles/test8.sud", "--json"]);
+    assert_eq!(code, 0);
+    let cell = |row, col, given, value| format!(
+        r#"{{"row":{},"col":{},"given":{},"solved":true,"value":"{}","candidates":[]}}"#,
+        row, col, given, value,
+    );
+    let solution = ["3124", "4213", "1432", "2341"];
+    let givens = ["0110", "1001", "1001", "0110"];
+    let mut cells = Vec::new();
+    for r in 0..4 {
+        for c in 0..4 {
+            let given = givens[r].as_bytes()[c] == b'1';
+            cells.push(cell(r + 1, c + 1, given, solution[r].as_bytes()[c] as char));
+        }
+    }
+    let expected = format!(
+        concat!(
+            r#"{{"status":"Solved","solutions":1,"conflicts":[],"violations":[],"#,
+            r#""grid":{{"name":"2025-11-26 Test Duodoku","state_dict":"1234","states":4,"#,
+            r#""isqrt":2,"box_width":2,"box_height":2,"regions":[],"variants":[],"#,
+            r#""constraints":[],"status":"Solved","cells":[{}]}}}}"#,
+            "\n",
+        ),
+        cells.join(","),
+    );
+    assert_eq!(out, expected);
+}
+
+// batch - run batch --no-time on the given one-line puzzles, written to a file of
+// their own so the tests can run side by side
+fn batch(name: &str, lines: &[&str], json: bool) -> (String, i32) {
+    let path = std::env::temp_dir().join(format!("sudoku-{}-{}.txt", name, std::process::id()));
+    batch_file(&path, lines, json)
+}
+
+// batch_file - the same, written to the file at path
+fn batch_file(path: &std::path::Path, lines: &[&str], json: bool) -> (String, i32) {
+    std::fs::write(path, lines.join("\n")).unwrap();
+    let path = path.to_str().unwrap();
+    let result = if json {
+        run(&["batch", path, "--no-time", "--json"])
+    } else {
+        run(&["batch", path, "--no-time"])
+    };
+    std::fs::remove_file(path).unwrap();
+    result
+}
+
+const SOLVABLE: &str = "-12-4--31--2-34-";
+const INVALID: &str = "11--------------";
+const UNSOLVABLE: &str = "1------------234";
+
+#[test]
+fn batch_reports_each_puzzle_and_the_worst_code() {
+    let (out, code) = batch("mixed", &["# mixed", SOLVABLE, INVALID, UNSOLVABLE], false);
+    assert_eq!(code, 3);
+    let statuses: Vec<&str> = out.lines().skip(1).take(3)
+        .map(|l| l.split_whitespace().nth(3).unwrap())
+        .collect();
+    assert_eq!(statuses, ["Solved", "Invalid", "Unsolvable"]);
+    assert!(out.contains("3 puzzles: 1 Solved, 1 Invalid, 1 Unsolvable\n"));
+
+    let (out, code) = batch("mixed-json", &[SOLVABLE, INVALID, UNSOLVABLE], true);
+    assert_eq!(code, 3);
+    let puzzles: Vec<&str> = out.split(r#"{"name":"#).skip(1).collect();
+    assert_eq!(puzzles.len(), 3);
+    for (puzzle, status) in puzzles.iter().zip(["Solved", "Invalid", "Unsolvable"]) {
+        assert!(puzzle.contains(&format!(r#""status":"{}""#, status)));
+        assert!(out.contains(&format!(r#"{{"status":"{}","count":1}}"#, status)));
+    }
+
+    assert_eq!(batch("invalid", &[SOLVABLE, INVALID], false).1, 2);
+    assert_eq!(batch("solvable", &[SOLVABLE, SOLVABLE], false).1, 0);
+}
+
+#[test]
+fn batch_carries_on_past_a_bad_line() {
+    let (out, code) = batch("bad", &[SOLVABLE, "-12-4", SOLVABLE], false);
+    assert_eq!(code, 1);
+    assert!(out.contains("3 puzzles: 2 Solved, 1 Error\n"));
+}
+
+#[test]
+fn batch_reads_a_sud_file_with_a_numeric_title() {
+    let text = std::fs::read_to_string("sudfiles/test8.sud").unwrap();
+    let text = text.replacen("2025-11-26 Test Duodoku", "1234123412341234", 1);
+    let path = std::env::temp_dir().join(format!("sudoku-numeric-{}.sud", std::process::id()));
+    let (out, code) = batch_file(&path, &[&text], false);
+    assert_eq!(code, 0);
+    assert!(out.contains("1 puzzles: 1 Solved\n"));
+}
+
+#[test]
+fn commands_exit_with_the_status_of_the_sample() {
+    let code = |args: &[&str]| run(args).1;
+    assert_eq!(code(&["solve", "sudfiles/test8.sud"]), 0);
+    assert_eq!(code(&["validate", "sudfiles/test2b.sud"]), 0);
+    assert_eq!(code(&["solve", "sudfiles/error2.sud"]), 2);
+    assert_eq!(code(&["validate", "sudfiles/test6.sud"]), 2);
+    assert_eq!(code(&["validate", "sudfiles/test8.sud"]), 4);
+    assert_eq!(code(&["generate", "--size", "7"]), 5);
+    assert_eq!(code(&["solve", "sudfiles/no-such-file.sud"]), 1);
+    assert_eq!(code(&["no-such-command"]), 1);
+}
+
+#[test]
+fn no_solution_and_several_share_code_3() {
+    let (out, code) = run(&["solve", "sudfiles/test1a.sud"]);
+    assert_eq!(code, 3);
+    assert!(out.contains("More than one solution\n"));
+    let (out, code) = run(&["solve", "sudfiles/test1a.sud", "--json"]);
+    assert_eq!(code, 3);
+    assert!(out.starts_with(r#"{"status":"Unsolvable","solutions":2,"#));
+
+    let path = std::env::temp_dir().join(format!("sudoku-none-{}.txt", std::process::id()));
+    std::fs::write(&path, UNSOLVABLE).unwrap();
+    let path = path.to_str().unwrap();
+    let (out, code) = run(&["solve", path]);
+    assert_eq!(code, 3);
+    assert!(out.contains("No solution\n"));
+    let (out, code) = run(&["solve", path, "--json"]);
+    assert_eq!(code, 3);
+    assert!(out.starts_with(r#"{"status":"Unsolvable","solutions":0,"#));
+    assert_eq!(run(&["rate", path]).1, 3);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn rate_agrees_with_solve() {
+    // several solutions, solved by logic, and unique but needing search
+    assert_eq!(run(&["rate", "sudfiles/test1a.sud"]).1, 3);
+    assert_eq!(run(&["rate", "sudfiles/test2a.sud"]).1, 0);
+    let (out, code) = run(&["rate", "sudfiles/killer1.sud"]);
+    assert_eq!(code, 4);
+    assert!(out.contains("(needs search to finish)"));
+}